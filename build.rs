@@ -0,0 +1,33 @@
+//! Embeds the commit and date of the build, shown by `--version` and in notifications.
+
+use std::{path::Path, process::Command};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+
+    // builds without a git checkout can pass the commit themselves
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+        })
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+
+    println!(
+        "cargo:rustc-env=BUILD_DATE={}",
+        chrono::Utc::now().format("%Y-%m-%d")
+    );
+}
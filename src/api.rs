@@ -0,0 +1,378 @@
+//! Reads schedules from a JSON endpoint, which breaks less often than scraping the HTML schedule pages.
+
+use log::{debug, warn};
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use chrono::DateTime;
+use serde::Deserialize;
+
+use crate::cinema::CinemaRef;
+use crate::error::MonitorError;
+use crate::notifications::Showtime;
+use crate::request::{MovieMonitorRequest, TIME_FORMAT};
+use crate::scrape::{
+    absolute_link, dedupe_showtimes, fetch_schedule, is_requested, parse_start, passes_filters,
+    scheduled_notification, ScheduleScan, ScheduledMovie, Selectors, PATHE_TZ, PREVIEW_MARKERS,
+};
+
+/// Where the schedule widget of pathe.nl loads its showtimes from, `{cinema}` and `{date}` are filled in.
+pub(crate) const DEFAULT_SCHEDULE_API_URL: &str =
+    "https://www.pathe.nl/api/cinema/schedules?cinemaId={cinema}&date={date}";
+
+/// Only the first failing JSON schedule is warned about with `auto`, it falls back to the HTML one anyway.
+static JSON_FALLBACK_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Where the schedules are read from, set through `SCHEDULE_SOURCE`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ScheduleSource {
+    /// scrape the schedule pages
+    #[default]
+    Html,
+    /// read the JSON schedules on `SCHEDULE_API_URL`
+    Json,
+    /// read the JSON schedules, and scrape the schedule page when the JSON one fails
+    Auto,
+}
+
+impl FromStr for ScheduleSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "html" => Ok(ScheduleSource::Html),
+            "json" => Ok(ScheduleSource::Json),
+            "auto" => Ok(ScheduleSource::Auto),
+            _ => Err(format!("unknown source '{}', use html, json or auto", s)),
+        }
+    }
+}
+
+impl Display for ScheduleSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ScheduleSource::Html => "html",
+            ScheduleSource::Json => "json",
+            ScheduleSource::Auto => "auto",
+        })
+    }
+}
+
+/// A JSON schedule, either the list of movies or an object holding it in `movies`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonSchedule {
+    Movies(Vec<JsonMovie>),
+    Object { movies: Vec<JsonMovie> },
+}
+
+#[derive(Deserialize)]
+struct JsonMovie {
+    title: String,
+    /// the link to the movie's page, e.g. `/films/12345-the-green-knight`
+    #[serde(default)]
+    href: Option<String>,
+    #[serde(default)]
+    poster: Option<String>,
+    #[serde(default)]
+    showtimes: Vec<JsonShowtime>,
+}
+
+#[derive(Deserialize)]
+struct JsonShowtime {
+    /// `HH:MM` on the date of the schedule, or an RFC 3339 timestamp
+    start: String,
+    end: String,
+    #[serde(default)]
+    label: String,
+    link: String,
+    #[serde(default)]
+    hall: Option<String>,
+    #[serde(default)]
+    availability: Option<String>,
+    #[serde(default)]
+    preview: bool,
+    #[serde(default = "default_true")]
+    bookable: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The JSON schedule of `cinema` on `date`, from an `api_url` with `{cinema}` and `{date}` placeholders.
+pub(crate) fn schedule_api_url(api_url: &str, cinema: &CinemaRef, date: &str) -> String {
+    api_url
+        .replace("{cinema}", &cinema.id().to_string())
+        .replace("{date}", date)
+}
+
+/// Whether a schedule body is JSON rather than HTML.
+pub(crate) fn is_json(body: &str) -> bool {
+    body.trim_start().starts_with(['{', '['])
+}
+
+/// `HH:MM` as it's listed on the schedule, from either `HH:MM` or an RFC 3339 timestamp.
+fn local_time(time: &str) -> String {
+    match DateTime::parse_from_rfc3339(time.trim()) {
+        Ok(time) => time
+            .with_timezone(&PATHE_TZ)
+            .format(TIME_FORMAT)
+            .to_string(),
+        Err(_) => time.trim().to_string(),
+    }
+}
+
+/// A fetched schedule, the JSON ones are parsed while fetching already to tell whether to fall back on the HTML one.
+#[derive(Clone, Debug)]
+pub(crate) enum FetchedSchedule {
+    Html(String),
+    Json {
+        body: String,
+        movies: Vec<ScheduledMovie>,
+    },
+}
+
+impl FetchedSchedule {
+    /// Reads a schedule body, parsing it when it's JSON.
+    pub(crate) fn read(date: &str, body: String) -> Result<Self, MonitorError> {
+        if is_json(&body) {
+            let movies = parse_json_schedule(date, &body)?;
+            Ok(FetchedSchedule::Json { body, movies })
+        } else {
+            Ok(FetchedSchedule::Html(body))
+        }
+    }
+
+    /// The body as it was fetched.
+    pub(crate) fn body(&self) -> &str {
+        match self {
+            FetchedSchedule::Html(body) | FetchedSchedule::Json { body, .. } => body,
+        }
+    }
+}
+
+/// Lists every movie on a JSON schedule like `list_schedule` lists those on an HTML one.
+pub(crate) fn parse_json_schedule(
+    date: &str,
+    body: &str,
+) -> Result<Vec<ScheduledMovie>, MonitorError> {
+    let movies =
+        match serde_json::from_str(body).map_err(|e| MonitorError::JsonSchedule(e.to_string()))? {
+            JsonSchedule::Movies(movies) | JsonSchedule::Object { movies } => movies,
+        };
+
+    Ok(movies
+        .into_iter()
+        .map(|movie| {
+            let mut showtimes: Vec<Showtime> = movie
+                .showtimes
+                .into_iter()
+                .map(|showtime| {
+                    let start = local_time(&showtime.start);
+                    let preview = showtime.preview
+                        || showtime.label.split_whitespace().any(|word| {
+                            let word = word.to_lowercase();
+                            PREVIEW_MARKERS.iter().any(|marker| word.contains(marker))
+                        });
+
+                    Showtime {
+                        starts_at: parse_start(date, &start),
+                        label: showtime.label,
+                        start,
+                        end: local_time(&showtime.end),
+                        link: absolute_link(&showtime.link),
+                        availability: showtime.availability.filter(|text| !text.is_empty()),
+                        hall: showtime.hall.filter(|hall| !hall.is_empty()),
+                        preview,
+                        bookable: showtime.bookable,
+                        cinema: None,
+                    }
+                })
+                .collect();
+            dedupe_showtimes(&mut showtimes);
+            showtimes.sort_by_key(|showtime| (showtime.starts_at.is_none(), showtime.starts_at));
+
+            ScheduledMovie {
+                title: movie.title,
+                href: movie.href,
+                poster: movie.poster,
+                showtimes,
+            }
+        })
+        .collect())
+}
+
+/// Finds the movies on a JSON schedule that match the request, like `scan_schedule` does on an HTML one.
+pub(crate) fn scan_json_schedule(
+    request: &MovieMonitorRequest,
+    cinema: &CinemaRef,
+    date: &str,
+    movies: &[ScheduledMovie],
+) -> Option<ScheduleScan> {
+    if movies.is_empty() {
+        return None;
+    }
+
+    let notifications = movies
+        .iter()
+        .filter(|movie| is_requested(request, &movie.title, movie.href.as_deref()))
+        .map(|movie| scheduled_notification(request, cinema, date, movie))
+        .filter(passes_filters)
+        .collect();
+
+    Some(ScheduleScan {
+        notifications,
+        incomplete: false,
+    })
+}
+
+/// Fetches the schedule of `cinema` on `date` from `source`, as a JSON or an HTML one.
+pub(crate) async fn fetch_cinema_schedule<F, Fut>(
+    cinema: &CinemaRef,
+    date: &str,
+    selectors: &Selectors,
+    source: ScheduleSource,
+    api_url: &str,
+    fetch: F,
+) -> Result<FetchedSchedule, MonitorError>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<String, MonitorError>>,
+{
+    if source != ScheduleSource::Html {
+        let url = schedule_api_url(api_url, cinema, date);
+        let result = fetch(url.clone()).await.and_then(|body| {
+            if !is_json(&body) {
+                return Err(MonitorError::JsonSchedule(format!(
+                    "`{}` didn't respond with JSON",
+                    url
+                )));
+            }
+            FetchedSchedule::read(date, body)
+        });
+
+        match result {
+            Ok(schedule) => return Ok(schedule),
+            Err(e) if source == ScheduleSource::Auto => {
+                if JSON_FALLBACK_WARNED.swap(true, Ordering::Relaxed) {
+                    debug!(
+                        "The JSON schedule of {} on {} failed, scraping the schedule pages instead: {}",
+                        cinema, date, e
+                    );
+                } else {
+                    warn!(
+                        "The JSON schedule of {} on {} failed, scraping the schedule pages instead: {}",
+                        cinema, date, e
+                    );
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    fetch_schedule(&cinema.schedule_url(date), selectors, fetch)
+        .await
+        .map(FetchedSchedule::Html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::cinema::Cinema;
+    use crate::testing::{request, SCHEDULE};
+
+    const JSON_SCHEDULE: &str = r#"{
+        "movies": [
+            {
+                "title": "The Green Knight",
+                "href": "/films/12345-the-green-knight",
+                "poster": "https://pathe.nl/posters/the-green-knight.jpg",
+                "showtimes": [
+                    { "start": "2021-08-19T19:00:00+02:00", "end": "21:10", "label": "OV", "link": "/tickets/2", "hall": "Zaal 6" },
+                    { "start": "14:30", "end": "16:40", "label": "NL", "link": "/tickets/1", "bookable": false },
+                    { "start": "14:30", "end": "16:40", "label": "NL", "link": "/tickets/1", "bookable": false }
+                ]
+            },
+            { "title": "Dune", "showtimes": [] }
+        ]
+    }"#;
+
+    #[test]
+    fn json_schedules_are_matched_like_html_ones() {
+        let cinema = CinemaRef::Known(Cinema::Spuimarkt);
+        let movies = parse_json_schedule("19-08-2021", JSON_SCHEDULE).unwrap();
+        let scan = scan_json_schedule(&request(), &cinema, "19-08-2021", &movies).unwrap();
+
+        assert_eq!(scan.notifications.len(), 1);
+        let notification = &scan.notifications[0];
+        assert_eq!(
+            notification.url,
+            "https://pathe.nl/films/12345-the-green-knight#agenda"
+        );
+        let showtimes: Vec<(&str, &str, bool, Option<&str>)> = notification
+            .showtimes
+            .iter()
+            .map(|showtime| {
+                (
+                    showtime.start.as_str(),
+                    showtime.link.as_str(),
+                    showtime.bookable,
+                    showtime.hall.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            showtimes,
+            vec![
+                ("14:30", "https://pathe.nl/tickets/1", false, None),
+                ("19:00", "https://pathe.nl/tickets/2", true, Some("Zaal 6")),
+            ]
+        );
+
+        let movies = parse_json_schedule("19-08-2021", &json!([]).to_string()).unwrap();
+        assert!(scan_json_schedule(&request(), &cinema, "19-08-2021", &movies).is_none());
+        assert!(parse_json_schedule("19-08-2021", "{}").is_err());
+    }
+
+    #[tokio::test]
+    async fn auto_falls_back_to_the_schedule_page() {
+        let cinema = CinemaRef::Known(Cinema::Spuimarkt);
+        let fetch = |url: String| async move {
+            match url.contains("/api/") {
+                true => Ok("<html>Not found</html>".to_string()),
+                false => Ok(SCHEDULE.to_string()),
+            }
+        };
+
+        let schedule = fetch_cinema_schedule(
+            &cinema,
+            "19-08-2021",
+            &Selectors::default(),
+            ScheduleSource::Auto,
+            DEFAULT_SCHEDULE_API_URL,
+            fetch,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(schedule, FetchedSchedule::Html(body) if body == SCHEDULE));
+
+        let error = fetch_cinema_schedule(
+            &cinema,
+            "19-08-2021",
+            &Selectors::default(),
+            ScheduleSource::Json,
+            DEFAULT_SCHEDULE_API_URL,
+            fetch,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("didn't respond with JSON"));
+    }
+}
@@ -0,0 +1,327 @@
+use log::warn;
+
+use reqwest::Client;
+use std::{fmt::Display, str::FromStr};
+
+use scraper::Html;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::config::MovieMonitorConfig;
+use crate::error::MonitorError;
+use crate::locale::LOCALE;
+use crate::scrape::{parse_selector, Selectors};
+
+pub(crate) const CINEMA_LIST_URL: &str = "https://www.pathe.nl/bioscoopagenda";
+
+/// A well-known cinema, written by its name in the config and sent to Pathé by its `cinemaId`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cinema {
+    Buitenhof = 7,
+    Spuimarkt = 13,
+    Delft = 18,
+}
+
+impl Cinema {
+    pub(crate) const ALL: [Cinema; 3] = [Cinema::Buitenhof, Cinema::Spuimarkt, Cinema::Delft];
+
+    /// The name used in the config, e.g. `Buitenhof`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Cinema::Buitenhof => "Buitenhof",
+            Cinema::Spuimarkt => "Spuimarkt",
+            Cinema::Delft => "Delft",
+        }
+    }
+
+    /// The `cinemaId` Pathé expects.
+    pub(crate) fn id(&self) -> u32 {
+        *self as u32
+    }
+}
+
+/// Parses a known name, ignoring case.
+impl FromStr for Cinema {
+    type Err = MonitorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Cinema::ALL
+            .iter()
+            .copied()
+            .find(|cinema| cinema.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| MonitorError::UnknownCinema(s.to_string()))
+    }
+}
+
+impl Serialize for Cinema {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cinema {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Display for Cinema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format!("Pathé {}", self.name()))
+    }
+}
+
+/// Either one of the well-known `Cinema` variants or any other Pathé cinema by its `cinemaId`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum CinemaRef {
+    Known(Cinema),
+    Custom { id: u32, name: Option<String> },
+}
+
+/// How a `CinemaRef` is written in the config, parsed separately so an unknown name is reported as such instead of as
+/// not matching any variant.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCinemaRef {
+    Known(String),
+    Custom { id: u32, name: Option<String> },
+}
+
+impl<'de> Deserialize<'de> for CinemaRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match RawCinemaRef::deserialize(deserializer)? {
+            RawCinemaRef::Known(name) => {
+                CinemaRef::Known(name.parse::<Cinema>().map_err(serde::de::Error::custom)?)
+            }
+            RawCinemaRef::Custom { id, name } => CinemaRef::Custom { id, name },
+        })
+    }
+}
+
+impl CinemaRef {
+    pub(crate) fn id(&self) -> u32 {
+        match self {
+            CinemaRef::Known(cinema) => cinema.id(),
+            CinemaRef::Custom { id, .. } => *id,
+        }
+    }
+
+    /// Whether the cinema is on the cinema list Pathé serves.
+    pub(crate) fn is_listed(&self, cinemas: &[CinemaInfo]) -> bool {
+        cinemas.iter().any(|cinema| cinema.id == self.id())
+    }
+
+    /// The schedule of the cinema on `date`, which Pathé expects in `DATE_FORMAT`.
+    pub(crate) fn schedule_url(&self, date: &str) -> String {
+        format!(
+            "https://www.pathe.nl/cinema/schedules?cinemaId={cinema_id}&date={date}",
+            cinema_id = self.id(),
+            date = date
+        )
+    }
+}
+
+/// Parses a known name, ignoring case, or else the ID of any Pathé cinema.
+impl FromStr for CinemaRef {
+    type Err = MonitorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse() {
+            Ok(id) => Ok(CinemaRef::Custom { id, name: None }),
+            Err(_) => s.parse().map(CinemaRef::Known),
+        }
+    }
+}
+
+impl Display for CinemaRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CinemaRef::Known(cinema) => cinema.fmt(f),
+            CinemaRef::Custom {
+                name: Some(name), ..
+            } => f.write_str(&format!("Pathé {}", name)),
+            CinemaRef::Custom { id, name: None } => f.write_str(&format!("Pathé #{}", id)),
+        }
+    }
+}
+
+/// One cinema or a list of cinemas, a movie showing at any of them matches.
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum CinemaSpec {
+    Single(CinemaRef),
+    List(Vec<CinemaRef>),
+}
+
+impl CinemaSpec {
+    pub(crate) fn cinemas(&self) -> &[CinemaRef] {
+        match self {
+            CinemaSpec::Single(cinema) => std::slice::from_ref(cinema),
+            CinemaSpec::List(cinemas) => cinemas,
+        }
+    }
+
+    /// Whether the showtimes of the cinemas are notified together, each named by its cinema.
+    pub(crate) fn is_multiple(&self) -> bool {
+        self.cinemas().len() > 1
+    }
+}
+
+/// Parsed by its shape first, so an unknown name in a list is reported as such.
+impl<'de> Deserialize<'de> for CinemaSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Ok(if value.is_array() {
+            CinemaSpec::List(Vec::deserialize(value).map_err(serde::de::Error::custom)?)
+        } else {
+            CinemaSpec::Single(CinemaRef::deserialize(value).map_err(serde::de::Error::custom)?)
+        })
+    }
+}
+
+impl Display for CinemaSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cinemas: Vec<String> = self
+            .cinemas()
+            .iter()
+            .map(|cinema| cinema.to_string())
+            .collect();
+        f.write_str(&cinemas.join(LOCALE.or()))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct CinemaInfo {
+    pub(crate) id: u32,
+    pub(crate) name: String,
+    pub(crate) city: String,
+}
+
+impl Display for CinemaInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{id}: {name} ({city})",
+            id = self.id,
+            name = self.name,
+            city = self.city,
+        ))
+    }
+}
+
+/// Scrapes the cinema picker on the schedule page, which groups every cinema per city.
+pub(crate) async fn fetch_cinema_list(
+    client: &Client,
+    selectors: &Selectors,
+) -> Result<Vec<CinemaInfo>, MonitorError> {
+    let body = client.get(CINEMA_LIST_URL).send().await?.text().await?;
+    let document = Html::parse_document(&body);
+
+    let city_selector = parse_selector(&selectors.cinema_group)?;
+    let cinema_selector = parse_selector(&selectors.cinema_option)?;
+
+    let mut cinemas = vec![];
+
+    for city in document.select(&city_selector) {
+        let city_name = city.value().attr("label").unwrap_or_default().trim();
+
+        for cinema in city.select(&cinema_selector) {
+            let id = cinema.value().attr("value").and_then(|id| id.parse().ok());
+
+            if let Some(id) = id {
+                cinemas.push(CinemaInfo {
+                    id,
+                    name: cinema.text().collect::<String>().trim().to_string(),
+                    city: city_name.to_string(),
+                });
+            }
+        }
+    }
+
+    if cinemas.is_empty() {
+        return Err(MonitorError::MissingElement(format!(
+            "{} {}",
+            selectors.cinema_group, selectors.cinema_option
+        )));
+    }
+
+    Ok(cinemas)
+}
+
+pub(crate) fn validate_cinemas(config: &MovieMonitorConfig, cinemas: &[CinemaInfo]) {
+    for request in &config.requests {
+        for cinema in request.cinema.cinemas() {
+            if !cinema.is_listed(cinemas) {
+                warn!(
+                    "{} refers to unknown cinema ID {}, it will never match",
+                    request,
+                    cinema.id()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cinemas_are_parsed_by_name_or_id() {
+        assert_eq!("spuimarkt".parse::<CinemaRef>().unwrap().id(), 13);
+        assert_eq!("Delft".parse::<CinemaRef>().unwrap().id(), 18);
+
+        let custom: CinemaRef = "42".parse().unwrap();
+        assert_eq!(custom.id(), 42);
+        assert_eq!(custom.to_string(), "Pathé #42");
+
+        assert!("Arena".parse::<CinemaRef>().is_err());
+    }
+
+    #[test]
+    fn cinemas_round_trip_by_name() {
+        for cinema in Cinema::ALL {
+            let json = serde_json::to_string(&CinemaRef::Known(cinema)).unwrap();
+            assert_eq!(json, format!("\"{}\"", cinema.name()));
+
+            match serde_json::from_str::<CinemaRef>(&json).unwrap() {
+                CinemaRef::Known(parsed) => assert_eq!(parsed, cinema),
+                custom => panic!("{} parsed as {:?}", json, custom),
+            }
+            assert_eq!(
+                serde_yaml::from_str::<Cinema>(&serde_yaml::to_string(&cinema).unwrap()).unwrap(),
+                cinema
+            );
+        }
+
+        assert_eq!(Cinema::Buitenhof.id(), 7);
+        assert_eq!(
+            CinemaRef::Known(Cinema::Delft).schedule_url("19-08-2021"),
+            "https://www.pathe.nl/cinema/schedules?cinemaId=18&date=19-08-2021"
+        );
+
+        let custom: CinemaRef = serde_json::from_str(r#"{ "id": 42, "name": "Arena" }"#).unwrap();
+        assert_eq!(custom.id(), 42);
+        assert_eq!(
+            serde_json::to_string(&custom).unwrap(),
+            r#"{"id":42,"name":"Arena"}"#
+        );
+
+        let error = serde_json::from_str::<CinemaRef>(r#""Arena""#).unwrap_err();
+        assert!(
+            error.to_string().contains("unknown cinema `Arena`"),
+            "{}",
+            error
+        );
+
+        let spec: CinemaSpec = serde_json::from_str(r#"["Delft", { "id": 42 }]"#).unwrap();
+        assert_eq!(spec.to_string(), "Pathé Delft of Pathé #42");
+        let error = serde_json::from_str::<CinemaSpec>(r#"["Delft", "Arena"]"#).unwrap_err();
+        assert!(
+            error.to_string().contains("unknown cinema `Arena`"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,648 @@
+//! The config file with the movie requests.
+
+use log::{debug, error, info, trace, warn};
+
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::error::MonitorError;
+use crate::request::{is_default, MovieMonitorRequest};
+use crate::scrape::Selectors;
+
+pub const CONFIG_FILE: &str = "config.json";
+/// The shape of the config file this version writes, older files are migrated when they are read.
+pub(crate) const CONFIG_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct MovieMonitorConfig {
+    /// the shape of the file, files from before the version was introduced are version 1
+    #[serde(default = "unversioned")]
+    pub(crate) version: u32,
+    /// drop requests from the config file once all their dates have passed
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub(crate) auto_prune: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub(crate) selectors: Selectors,
+    /// the titles Pathé also uses for a movie, e.g. a working title, matched as if the request named them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) aliases: BTreeMap<String, Vec<String>>,
+    pub(crate) requests: Vec<MovieMonitorRequest>,
+}
+
+fn unversioned() -> u32 {
+    1
+}
+
+impl MovieMonitorConfig {
+    /// Upgrades a config written by an older version to `CONFIG_VERSION`, returns the version it was migrated from.
+    pub(crate) fn migrate(&mut self) -> Result<Option<u32>, MonitorError> {
+        let from = self.version;
+        if from > CONFIG_VERSION {
+            return Err(MonitorError::UnsupportedConfigVersion(from));
+        }
+        if from == CONFIG_VERSION {
+            return Ok(None);
+        }
+
+        // version 1 only lacks the `version`, every field added since has a default
+        self.version = CONFIG_VERSION;
+
+        Ok(Some(from))
+    }
+
+    /// Drops the requests that repeat an earlier one, they would be scraped and notified twice. Requests are compared
+    /// by the IDs of their cinemas and their movies ignoring case, the notified showtimes don't matter.
+    pub(crate) fn dedupe_requests(&mut self) {
+        let key = |request: &MovieMonitorRequest| {
+            let mut key = json!(request);
+            key["cinema"] = json!(request
+                .cinema
+                .cinemas()
+                .iter()
+                .map(|cinema| cinema.id())
+                .collect::<Vec<_>>());
+            key["movie"] = json!(request
+                .movie
+                .titles()
+                .iter()
+                .map(|movie| movie.trim().to_lowercase())
+                .collect::<Vec<_>>());
+            if let Some(key) = key.as_object_mut() {
+                key.remove("seen_showtimes");
+                key.remove("last_notified");
+            }
+            key
+        };
+
+        let mut keys = vec![];
+        self.requests.retain(|request| {
+            let key = key(request);
+            if keys.contains(&key) {
+                warn!("Skipping {}, it duplicates an earlier request", request);
+                return false;
+            }
+            keys.push(key);
+            true
+        });
+    }
+
+    /// Hands every request the aliases of its movies, the names are compared ignoring case.
+    pub(crate) fn apply_aliases(&mut self) {
+        let all_aliases = &self.aliases;
+        for request in &mut self.requests {
+            request.aliases = request
+                .movie
+                .titles()
+                .iter()
+                .filter_map(|movie| {
+                    let aliases: Vec<String> = all_aliases
+                        .iter()
+                        .filter(|(name, _)| {
+                            name.trim().to_lowercase() == movie.trim().to_lowercase()
+                        })
+                        .flat_map(|(_, aliases)| aliases.iter().cloned())
+                        .collect();
+                    (!aliases.is_empty()).then(|| (movie.clone(), aliases))
+                })
+                .collect();
+        }
+    }
+
+    /// Checks what deserializing can't, like whether every date and timezone can be resolved.
+    pub(crate) fn validate(&self) -> Result<(), MonitorError> {
+        self.selectors.validate()?;
+
+        let today = Utc::now().naive_utc().date();
+
+        for request in &self.requests {
+            request.validate(today)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the last valid config around and reloads it once the file is modified.
+pub(crate) struct ConfigWatcher {
+    pub(crate) path: String,
+    pub(crate) config: MovieMonitorConfig,
+    /// when the file was last loaded successfully
+    pub(crate) modified: Option<SystemTime>,
+    /// when the file was last modified into a config that failed to load, so it's only reported once
+    pub(crate) rejected: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub(crate) fn new(path: &str) -> Result<Self, MonitorError> {
+        let (config, migrated) = read_migrated_config(path)?;
+        config.validate()?;
+        write_back_migrated(path, &config, migrated);
+
+        Ok(ConfigWatcher {
+            path: path.to_string(),
+            config,
+            modified: modified_time(path),
+            rejected: None,
+        })
+    }
+
+    pub(crate) fn reload_if_changed(&mut self) {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified || modified == self.rejected {
+            return;
+        }
+
+        info!("`{}` changed, reloading", self.path);
+        let (config, migrated) = match read_migrated_config(&self.path).and_then(|loaded| {
+            loaded.0.validate()?;
+            Ok(loaded)
+        }) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!(
+                    "failed reloading `{}`, keeping the previous config: {}",
+                    self.path, e
+                );
+                self.rejected = modified;
+                return;
+            }
+        };
+        write_back_migrated(&self.path, &config, migrated);
+        self.modified = match migrated {
+            Some(_) => modified_time(&self.path),
+            None => modified,
+        };
+        self.rejected = None;
+
+        let as_json = |requests: &[MovieMonitorRequest]| -> Vec<serde_json::Value> {
+            requests.iter().map(|request| json!(request)).collect()
+        };
+        let old = as_json(&self.config.requests);
+        let new = as_json(&config.requests);
+
+        for (request, value) in config.requests.iter().zip(&new) {
+            if !old.contains(value) {
+                info!("Added {}", request);
+            }
+        }
+        for (request, value) in self.config.requests.iter().zip(&old) {
+            if !new.contains(value) {
+                info!("Removed {}", request);
+            }
+        }
+
+        self.config = config;
+    }
+
+    /// Writes the config back, unless the file was edited since it was last loaded successfully, e.g. into a config
+    /// that doesn't load, which would be overwritten with the previous one.
+    pub(crate) fn save(&mut self) {
+        if modified_time(&self.path) != self.modified {
+            warn!(
+                "`{}` changed since it was loaded, not writing back the notified showtimes",
+                self.path
+            );
+            return;
+        }
+
+        // the config in memory is still up to date, the next check tries again
+        if let Err(e) = write_config_to_file(&self.path, &self.config) {
+            error!("Could not write back the notified showtimes: {}", e);
+            return;
+        }
+        self.modified = modified_time(&self.path);
+    }
+
+    /// Forgets which showtimes have been notified.
+    pub(crate) fn reset_state(&mut self) {
+        for request in &mut self.config.requests {
+            request.seen_showtimes.clear();
+            request.last_notified = None;
+        }
+        self.save();
+        info!("Cleared the notified showtimes in `{}`", self.path);
+    }
+}
+
+pub(crate) fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// The formats the config file can be written in, picked by its extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// `.yaml` and `.yml` files are YAML, `.toml` files TOML, anything else is JSON.
+    pub(crate) fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        match extension.as_deref() {
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+pub(crate) fn read_config_from_file(path: &str) -> Result<MovieMonitorConfig, MonitorError> {
+    read_migrated_config(path).map(|(config, _)| config)
+}
+
+/// Reads the config like `read_config_from_file`, a config of an older version is only migrated in memory. Returns
+/// the version it was migrated from, so the monitor can write it back.
+pub(crate) fn read_migrated_config(
+    path: &str,
+) -> Result<(MovieMonitorConfig, Option<u32>), MonitorError> {
+    trace!("reading config from `{}`", path);
+    let file = File::open(path);
+
+    if file.is_err() {
+        warn!("`{}` not found, generating a fresh one", path);
+        let config = MovieMonitorConfig {
+            version: CONFIG_VERSION,
+            auto_prune: false,
+            selectors: Selectors::default(),
+            aliases: BTreeMap::new(),
+            requests: vec![],
+        };
+        if let Err(e) = write_config_to_file(path, &config) {
+            error!("Could not generate a fresh config: {}", e);
+        }
+
+        return Ok((config, None));
+    }
+
+    let mut reader = BufReader::new(file.unwrap());
+    let mut config: MovieMonitorConfig = match ConfigFormat::from_path(path) {
+        ConfigFormat::Json => serde_json::from_reader(reader)?,
+        ConfigFormat::Yaml => serde_yaml::from_reader(reader)?,
+        ConfigFormat::Toml => {
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents)?;
+            toml::from_str(&contents)?
+        }
+    };
+    let migrated = config.migrate()?;
+    if let Some(from) = migrated {
+        debug!(
+            "Migrated `{}` from version {} to {}",
+            path, from, CONFIG_VERSION
+        );
+    }
+    config.dedupe_requests();
+    config.apply_aliases();
+
+    Ok((config, migrated))
+}
+
+/// Writes a migrated config back, so it's only migrated once.
+fn write_back_migrated(path: &str, config: &MovieMonitorConfig, from: Option<u32>) {
+    if let Some(from) = from {
+        info!(
+            "Migrated `{}` from version {} to {}, writing it back",
+            path, from, CONFIG_VERSION
+        );
+        if let Err(e) = write_config_to_file(path, config) {
+            warn!("Could not write back the migrated config: {}", e);
+        }
+    }
+}
+
+/// Writes the config to a temporary file next to `path` first and then moves it over `path`, so a crash while writing
+/// leaves either the old or the new config behind instead of half of it.
+pub(crate) fn write_config_to_file(
+    path: &str,
+    config: &MovieMonitorConfig,
+) -> Result<(), MonitorError> {
+    debug!("writing new config to `{}`", path);
+    let failed =
+        |e: &dyn std::fmt::Display| MonitorError::WriteConfig(format!("`{}`: {}", path, e));
+
+    let contents = match ConfigFormat::from_path(path) {
+        ConfigFormat::Json => serde_json::to_string_pretty(config).map_err(|e| failed(&e))?,
+        ConfigFormat::Yaml => serde_yaml::to_string(config).map_err(|e| failed(&e))?,
+        ConfigFormat::Toml => toml::to_string_pretty(config).map_err(|e| failed(&e))?,
+    };
+
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|e| failed(&e))?;
+        }
+    }
+
+    let temporary = format!("{}.tmp", path);
+    let written = File::create(&temporary).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        writer.write_all(contents.as_bytes())?;
+        writer.into_inner()?.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+        return Err(failed(&e));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::SPUIMARKT;
+
+    #[test]
+    fn yaml_and_json_configs_are_equivalent() {
+        assert_eq!(ConfigFormat::from_path("config.json"), ConfigFormat::Json);
+        assert_eq!(
+            ConfigFormat::from_path("/config/config.YML"),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(ConfigFormat::from_path("config"), ConfigFormat::Json);
+
+        let yaml: MovieMonitorConfig = serde_yaml::from_str(
+            r#"
+requests:
+  - cinema: Spuimarkt
+    date: { from: today, to: +3d }
+    movie: [Thor, Dune]
+    versions: [OV]
+"#,
+        )
+        .unwrap();
+        let json: MovieMonitorConfig = serde_json::from_value(json!({
+            "requests": [{
+                "cinema": "Spuimarkt",
+                "date": { "from": "today", "to": "+3d" },
+                "movie": ["Thor", "Dune"],
+                "versions": ["OV"]
+            }]
+        }))
+        .unwrap();
+        assert_eq!(json!(yaml), json!(json));
+
+        let written = serde_yaml::to_string(&yaml).unwrap();
+        let read: MovieMonitorConfig = serde_yaml::from_str(&written).unwrap();
+        assert_eq!(json!(read), json!(json));
+    }
+
+    #[test]
+    fn malformed_reloads_keep_the_previous_config() {
+        let path = std::env::temp_dir().join(format!("config-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            r#"{ "requests": [{ "cinema": "Delft", "date": "today", "movie": "Dune" }] }"#,
+        )
+        .unwrap();
+        let mut watcher = ConfigWatcher::new(path).unwrap();
+
+        fs::write(path, r#"{ "requests": [{ "cinema": "Delft", }] }"#).unwrap();
+        let error = read_config_from_file(path).unwrap_err().to_string();
+        assert!(error.contains("line 1 column"), "{}", error);
+
+        watcher.modified = None;
+        watcher.reload_if_changed();
+        assert_eq!(watcher.config.requests.len(), 1);
+        assert_eq!(watcher.config.requests[0].movie.titles(), ["Dune"]);
+
+        // the half-edited file isn't overwritten with the previous config
+        watcher.save();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            r#"{ "requests": [{ "cinema": "Delft", }] }"#
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_dates_name_the_request() {
+        let config: MovieMonitorConfig = serde_json::from_value(json!({
+            "requests": [{ "cinema": "Spuimarkt", "date": "2024-13-01", "movie": "Dune" }]
+        }))
+        .unwrap();
+
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.starts_with("invalid date `2024-13-01` for "));
+        assert!(error.contains("in Pathé Spuimarkt"));
+    }
+
+    #[test]
+    fn requests_without_a_title_or_with_an_invalid_webhook_are_invalid() {
+        let config: MovieMonitorConfig = serde_json::from_value(json!({
+            "requests": [
+                { "cinema": "Spuimarkt", "date": "today", "movie": "Dune" },
+                { "cinema": "Spuimarkt", "date": "today", "movie": [" "] },
+                { "cinema": "Delft", "date": "today", "movie": "Dune", "webhook_url": "discord" }
+            ]
+        }))
+        .unwrap();
+        let today = Utc::now().naive_utc().date();
+
+        let errors: Vec<String> = config
+            .requests
+            .iter()
+            .filter_map(|request| request.validate(today).err())
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("no movie title for "));
+        assert!(errors[1].starts_with("invalid `webhook_url` `discord` for "));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn toml_configs_round_trip() {
+        assert_eq!(
+            ConfigFormat::from_path("/config/config.toml"),
+            ConfigFormat::Toml
+        );
+
+        let toml: MovieMonitorConfig = toml::from_str(
+            r#"
+auto_prune = true
+
+[[requests]]
+cinema = "Spuimarkt"
+date = { from = "today", to = "+3d" }
+movie = ["Thor", "Dune"]
+versions = ["OV"]
+
+[[requests]]
+cinema = { id = 42 }
+date = "19-08-2021"
+movie = "The Green Knight"
+seen_showtimes = ["19-08-2021 | The Green Knight | OV 19:00 - 21:10"]
+"#,
+        )
+        .unwrap();
+        let json: MovieMonitorConfig = serde_json::from_value(json!({
+            "auto_prune": true,
+            "requests": [{
+                "cinema": "Spuimarkt",
+                "date": { "from": "today", "to": "+3d" },
+                "movie": ["Thor", "Dune"],
+                "versions": ["OV"]
+            }, {
+                "cinema": { "id": 42 },
+                "date": "19-08-2021",
+                "movie": "The Green Knight",
+                "seen_showtimes": ["19-08-2021 | The Green Knight | OV 19:00 - 21:10"]
+            }]
+        }))
+        .unwrap();
+        assert_eq!(json!(toml), json!(json));
+
+        let mut json = json;
+        json.requests[1].last_notified =
+            Some(chrono::DateTime::parse_from_rfc3339("2021-08-18T12:00:00+02:00").unwrap());
+        let written = toml::to_string_pretty(&json).unwrap();
+        let read: MovieMonitorConfig = toml::from_str(&written).unwrap();
+        assert_eq!(json!(read), json!(json));
+    }
+
+    #[test]
+    fn aliases_match_like_the_movie_they_belong_to() {
+        let mut config: MovieMonitorConfig = serde_json::from_value(json!({
+            "aliases": { "dune: part two": ["Dune 2", "Dune - Deel Twee"] },
+            "requests": [{
+                "cinema": "Spuimarkt",
+                "date": "today",
+                "movie": ["Dune: Part Two", "Thor"],
+                "match_mode": "Exact"
+            }, {
+                "cinema": "Delft",
+                "date": "today",
+                "movie": "Thor"
+            }]
+        }))
+        .unwrap();
+        config.apply_aliases();
+
+        let request = &config.requests[0];
+        assert_eq!(request.matching_movie("dune 2"), Some("Dune: Part Two"));
+        assert_eq!(
+            request.matching_movie("Dune - Deel Twee"),
+            Some("Dune: Part Two")
+        );
+        assert!(!request.matches("Dune"));
+        assert!(!config.requests[1].matches("Dune 2"));
+
+        // the aliases stay in the config, not in every request that is written back
+        assert!(json!(request).get("aliases").is_none());
+        assert_eq!(json!(config)["aliases"]["dune: part two"][0], "Dune 2");
+    }
+
+    #[test]
+    fn duplicate_requests_are_skipped() {
+        let mut config: MovieMonitorConfig = serde_json::from_value(json!({
+            "requests": [{
+                "cinema": "Spuimarkt",
+                "date": "19-08-2021",
+                "movie": "The Green Knight"
+            }, {
+                "cinema": { "id": SPUIMARKT.id() },
+                "date": "19-08-2021",
+                "movie": "the green knight ",
+                "seen_showtimes": ["19-08-2021 | The Green Knight | OV 19:00 - 21:10"]
+            }, {
+                "cinema": "Spuimarkt",
+                "date": "19-08-2021",
+                "movie": "The Green Knight",
+                "versions": ["OV"]
+            }, {
+                "cinema": "Delft",
+                "date": "19-08-2021",
+                "movie": "The Green Knight"
+            }]
+        }))
+        .unwrap();
+        config.dedupe_requests();
+
+        assert_eq!(config.requests.len(), 3);
+        assert!(config.requests[0].seen_showtimes.is_empty());
+        assert!(config.requests[1].versions.is_some());
+    }
+
+    #[test]
+    fn configs_are_written_through_a_temporary_file() {
+        let directory = std::env::temp_dir().join(format!("pathe-monitor-{}", std::process::id()));
+        let path = directory.join("config.yaml");
+        let path = path.to_str().unwrap();
+        let config: MovieMonitorConfig = serde_json::from_value(json!({
+            "version": CONFIG_VERSION,
+            "requests": [{ "cinema": "Delft", "date": "today", "movie": "Dune" }]
+        }))
+        .unwrap();
+
+        write_config_to_file(path, &config).unwrap();
+        let read = read_config_from_file(path).unwrap();
+        assert_eq!(json!(read), json!(config));
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+
+        // a directory that can't be created fails the write instead of panicking
+        let blocked = directory.join("config.yaml").join("config.json");
+        assert!(matches!(
+            write_config_to_file(blocked.to_str().unwrap(), &config),
+            Err(MonitorError::WriteConfig(_))
+        ));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn migrated_configs_are_only_written_back_by_the_monitor() {
+        let path = std::env::temp_dir().join(format!("unversioned-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let unversioned =
+            r#"{ "requests": [{ "cinema": "Delft", "date": "today", "movie": "Dune" }] }"#;
+        fs::write(path, unversioned).unwrap();
+
+        let config = read_config_from_file(path).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(fs::read_to_string(path).unwrap(), unversioned);
+
+        let watcher = ConfigWatcher::new(path).unwrap();
+        let written = read_migrated_config(path).unwrap();
+        assert_eq!(written.1, None);
+        assert_eq!(watcher.modified, modified_time(path));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unversioned_configs_are_migrated() {
+        let mut config: MovieMonitorConfig = serde_json::from_value(json!({
+            "requests": [{ "cinema": "Delft", "date": "today", "movie": "Dune" }]
+        }))
+        .unwrap();
+        assert_eq!(config.version, 1);
+        assert_eq!(config.migrate().unwrap(), Some(1));
+        assert_eq!(json!(config)["version"], CONFIG_VERSION);
+        assert_eq!(config.migrate().unwrap(), None);
+
+        config.version = CONFIG_VERSION + 1;
+        assert!(matches!(
+            config.migrate(),
+            Err(MonitorError::UnsupportedConfigVersion(_))
+        ));
+    }
+}
@@ -0,0 +1,63 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MonitorError {
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid selector `{0}`")]
+    ParseSelector(String),
+    #[error("missing element `{0}`")]
+    MissingElement(String),
+    #[error("invalid JSON schedule: {0}")]
+    JsonSchedule(String),
+    #[error("invalid config: {0}")]
+    Config(#[from] serde_json::Error),
+    #[error("invalid config: {0}")]
+    YamlConfig(#[from] serde_yaml::Error),
+    #[error("invalid config: {0}")]
+    TomlConfig(#[from] toml::de::Error),
+    #[error("config version {0} is newer than this pathe-monitor supports, upgrade it")]
+    UnsupportedConfigVersion(u32),
+    #[error("reading config failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("writing config failed: {0}")]
+    WriteConfig(String),
+    #[error("unknown cinema `{0}`, use Buitenhof, Spuimarkt, Delft or its ID")]
+    UnknownCinema(String),
+    #[error("cinema ID {0} is not on the Pathé cinema list")]
+    UnlistedCinema(u32),
+    #[error("invalid date `{0}`")]
+    InvalidDate(String),
+    #[error("invalid date `{date}` for {request}, use DD-MM-YYYY, YYYY-MM-DD, today, tomorrow or +<days>d")]
+    InvalidRequestDate { date: String, request: String },
+    #[error("no movie title for {0}")]
+    EmptyMovie(String),
+    #[error("invalid `webhook_url` `{url}` for {request}")]
+    InvalidWebhook { url: String, request: String },
+    #[error("empty `on_match_command` for {0}")]
+    EmptyCommand(String),
+    #[error("invalid time `{time}` for {request}, use HH:MM")]
+    InvalidTime { time: String, request: String },
+    #[error("invalid quiet hours `{0}`, use HH:MM-HH:MM")]
+    InvalidQuietHours(String),
+    #[error("unknown timezone `{timezone}` for {request}")]
+    InvalidTimezone { timezone: String, request: String },
+    #[error("no notifier configured for {0}")]
+    NoNotifier(String),
+    #[error("no notifier delivered the notification about {0}")]
+    NotDelivered(String),
+    #[error("took longer than {0:?}, abandoned it")]
+    Timeout(std::time::Duration),
+    #[error("Pathé keeps failing, not calling it for another {0}s")]
+    CircuitOpen(u64),
+    #[error("{0}")]
+    Webhook(String),
+    #[error("loading TLS certificates failed: {0}")]
+    Certificate(String),
+    #[error("status server failed: {0}")]
+    Server(String),
+    #[error("sending email failed: {0}")]
+    Email(String),
+    #[error("`on_match_command` failed: {0}")]
+    Command(String),
+}
@@ -0,0 +1,48 @@
+//! Monitors the Pathé schedule for movie requests and notifies when tickets are available.
+//!
+//! A request describes which movie to look for, on which date and in which cinema:
+//!
+//! ```
+//! use pathe_monitor::MovieMonitorRequest;
+//!
+//! let request: MovieMonitorRequest = serde_json::from_str(
+//!     r#"{"cinema": "Spuimarkt", "date": "01-03-2024", "movie": "Dune"}"#,
+//! )
+//! .unwrap();
+//! assert!(request.matches("Dune: Part Two"));
+//! ```
+
+extern crate chrono;
+extern crate chrono_tz;
+
+pub mod api;
+pub mod cinema;
+pub mod config;
+pub mod error;
+pub mod locale;
+pub mod logging;
+pub mod monitor;
+pub mod notifications;
+pub mod request;
+pub mod scrape;
+pub mod settings;
+pub mod status;
+#[cfg(test)]
+mod testing;
+pub mod wizard;
+
+/// The version, commit and build date, e.g. `0.1.0 (1a2b3c4, 2024-03-01)`.
+pub const BUILD_INFO: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GIT_COMMIT"),
+    ", ",
+    env!("BUILD_DATE"),
+    ")"
+);
+
+pub use cinema::Cinema;
+pub use error::MonitorError;
+pub use notifications::{Notification, Notifier};
+pub use request::MovieMonitorRequest;
+pub use scrape::{check_response, generate_notification};
@@ -0,0 +1,215 @@
+//! The language of the notifications, set through `LOCALE`.
+
+use std::{env, fmt::Display, str::FromStr, sync::LazyLock};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+
+use crate::request::DATE_FORMAT;
+
+/// The languages notifications can be written in, Dutch unless `LOCALE` says otherwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Locale {
+    Nl,
+    En,
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// `nl` or `en`, a region like `en-GB` or `nl_NL.UTF-8` is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s
+            .split(|c: char| !c.is_ascii_alphabetic())
+            .next()
+            .unwrap_or_default();
+        match language.to_lowercase().as_str() {
+            "nl" => Ok(Locale::Nl),
+            "en" => Ok(Locale::En),
+            _ => Err(format!("unsupported locale '{}', use nl or en", s)),
+        }
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Locale::Nl => "nl",
+            Locale::En => "en",
+        })
+    }
+}
+
+/// The language of the notifications, from `LOCALE`, which is validated at startup.
+pub(crate) static LOCALE: LazyLock<Locale> = LazyLock::new(|| {
+    env::var("LOCALE")
+        .ok()
+        .and_then(|locale| locale.parse().ok())
+        .unwrap_or(Locale::Nl)
+});
+
+impl Locale {
+    /// The first line of a notification, unless the request or `MESSAGE_TEMPLATE` has another.
+    pub(crate) fn message_template(self) -> &'static str {
+        match self {
+            Locale::Nl => {
+                "Er zijn tickets beschikbaar voor '{movie}' op {date} in {cinema}, {count}."
+            }
+            Locale::En => "Tickets are available for '{movie}' on {date} at {cinema}, {count}.",
+        }
+    }
+
+    pub(crate) fn showtime_count(self, count: usize) -> String {
+        match (self, count) {
+            (Locale::Nl, 1) => "1 voorstelling beschikbaar".to_string(),
+            (Locale::Nl, count) => format!("{} voorstellingen beschikbaar", count),
+            (Locale::En, 1) => "1 showing available".to_string(),
+            (Locale::En, count) => format!("{} showings available", count),
+        }
+    }
+
+    pub(crate) fn subject(self, titles: &str) -> String {
+        match self {
+            Locale::Nl => format!("Tickets beschikbaar voor {}", titles),
+            Locale::En => format!("Tickets available for {}", titles),
+        }
+    }
+
+    pub(crate) fn no_longer_available(self, title: &str, date: &str, cinema: &str) -> String {
+        match self {
+            Locale::Nl => format!(
+                "'{}' is niet meer beschikbaar op {} in {}.",
+                title, date, cinema
+            ),
+            Locale::En => format!(
+                "'{}' is no longer available on {} at {}.",
+                title, date, cinema
+            ),
+        }
+    }
+
+    pub(crate) fn startup(self, requests: usize) -> String {
+        match self {
+            Locale::Nl => format!(
+                "Pathé monitor is gestart en houdt {} verzoek(en) in de gaten:",
+                requests
+            ),
+            Locale::En => format!(
+                "Pathé monitor has started and is watching {} request(s):",
+                requests
+            ),
+        }
+    }
+
+    /// The text of an error reported to `ERROR_WEBHOOK_URL`.
+    pub(crate) fn check_failed(self, message: &str) -> String {
+        match self {
+            Locale::Nl => format!("⚠️ Controleren mislukt: {}", message),
+            Locale::En => format!("⚠️ Checking failed: {}", message),
+        }
+    }
+
+    /// What a request is about, e.g. `'Dune' op 01-03-2024 in Pathé Delft`.
+    pub(crate) fn request(self, movie: &str, date: &str, cinema: &str) -> String {
+        match self {
+            Locale::Nl => format!("{} op {} in {}", movie, date, cinema),
+            Locale::En => format!("{} on {} at {}", movie, date, cinema),
+        }
+    }
+
+    /// Joins the alternatives of a request, like its movies or cinemas.
+    pub(crate) fn or(self) -> &'static str {
+        match self {
+            Locale::Nl => " of ",
+            Locale::En => " or ",
+        }
+    }
+
+    pub(crate) fn date_range(self, from: &str, to: &str) -> String {
+        match self {
+            Locale::Nl => format!("{} t/m {}", from, to),
+            Locale::En => format!("{} to {}", from, to),
+        }
+    }
+
+    pub(crate) fn any_movie(self) -> &'static str {
+        match self {
+            Locale::Nl => "elke nieuwe film",
+            Locale::En => "every new movie",
+        }
+    }
+
+    pub(crate) fn preview(self) -> &'static str {
+        match self {
+            Locale::Nl => "voorpremière",
+            Locale::En => "preview",
+        }
+    }
+
+    pub(crate) fn almost_sold_out(self) -> &'static str {
+        match self {
+            Locale::Nl => "bijna uitverkocht",
+            Locale::En => "almost sold out",
+        }
+    }
+
+    pub(crate) fn sold_out(self) -> &'static str {
+        match self {
+            Locale::Nl => "uitverkocht",
+            Locale::En => "sold out",
+        }
+    }
+
+    /// The day of the week of a `DD-MM-YYYY` date, e.g. `donderdag`, or nothing when it can't be parsed.
+    pub(crate) fn weekday(self, date: &str) -> String {
+        let weekday = match NaiveDate::parse_from_str(date, DATE_FORMAT) {
+            Ok(date) => date.weekday(),
+            Err(_) => return String::new(),
+        };
+
+        match self {
+            Locale::Nl => match weekday {
+                Weekday::Mon => "maandag",
+                Weekday::Tue => "dinsdag",
+                Weekday::Wed => "woensdag",
+                Weekday::Thu => "donderdag",
+                Weekday::Fri => "vrijdag",
+                Weekday::Sat => "zaterdag",
+                Weekday::Sun => "zondag",
+            },
+            Locale::En => match weekday {
+                Weekday::Mon => "Monday",
+                Weekday::Tue => "Tuesday",
+                Weekday::Wed => "Wednesday",
+                Weekday::Thu => "Thursday",
+                Weekday::Fri => "Friday",
+                Weekday::Sat => "Saturday",
+                Weekday::Sun => "Sunday",
+            },
+        }
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales_parse_without_their_region() {
+        assert_eq!("nl_NL.UTF-8".parse(), Ok(Locale::Nl));
+        assert_eq!("en-GB".parse(), Ok(Locale::En));
+        assert!("de".parse::<Locale>().is_err());
+
+        assert_eq!(Locale::Nl.weekday("19-08-2021"), "donderdag");
+        assert_eq!(Locale::En.weekday("19-08-2021"), "Thursday");
+        assert_eq!(Locale::En.weekday("today"), "");
+        assert_eq!(
+            Locale::En.request("'Dune'", "19-08-2021", "Pathé Delft"),
+            "'Dune' on 19-08-2021 at Pathé Delft"
+        );
+        assert_eq!(
+            Locale::En.check_failed("timed out"),
+            "⚠️ Checking failed: timed out"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Coalesces repeated warnings and errors, so an outage doesn't bury everything else in the logs.
+
+use log::{Level, Log, Metadata, Record};
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+type MessageKey = (Level, String, String);
+
+/// Writes an identical warning or error at most once per `window`. The next time it's written, it mentions how often
+/// it was suppressed in between.
+pub struct ThrottledLog {
+    inner: Box<dyn Log>,
+    window: Duration,
+    /// when a message was last written and how often it has been suppressed since
+    seen: Mutex<HashMap<MessageKey, (Instant, usize)>>,
+}
+
+impl ThrottledLog {
+    /// A `window` of zero writes every message.
+    pub fn new(inner: Box<dyn Log>, window: Duration) -> Self {
+        ThrottledLog {
+            inner,
+            window,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How many repeats were suppressed since the message was last written, or `None` to suppress this one too.
+    pub(crate) fn admit(
+        &self,
+        level: Level,
+        target: &str,
+        message: &str,
+        now: Instant,
+    ) -> Option<usize> {
+        if level > Level::Warn || self.window.is_zero() {
+            return Some(0);
+        }
+
+        let window = self.window;
+        let mut seen = self.seen.lock().unwrap();
+        let key = (level, target.to_string(), message.to_string());
+        if let Some((since, suppressed)) = seen.get_mut(&key) {
+            if now.duration_since(*since) < window {
+                *suppressed += 1;
+                return None;
+            }
+        }
+
+        let suppressed = seen.remove(&key).map_or(0, |(_, suppressed)| suppressed);
+        seen.retain(|_, (since, suppressed)| {
+            *suppressed > 0 || now.duration_since(*since) < window
+        });
+        seen.insert(key, (now, 0));
+        Some(suppressed)
+    }
+}
+
+impl Log for ThrottledLog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        match self.admit(record.level(), record.target(), &message, Instant::now()) {
+            None => {}
+            Some(0) => self.inner.log(record),
+            Some(suppressed) => self.inner.log(
+                &Record::builder()
+                    .args(format_args!(
+                        "{} (suppressed {} repeats in the last {:?})",
+                        message, suppressed, self.window
+                    ))
+                    .metadata(record.metadata().clone())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoLog;
+
+    impl Log for NoLog {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, _record: &Record) {}
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn repeated_errors_are_suppressed_within_the_window() {
+        let log = ThrottledLog::new(Box::new(NoLog), Duration::from_secs(600));
+        let start = Instant::now();
+        let admit = |level: Level, message: &str, after: u64| {
+            log.admit(
+                level,
+                "pathe_monitor::monitor",
+                message,
+                start + Duration::from_secs(after),
+            )
+        };
+
+        assert_eq!(admit(Level::Error, "Pathé is down", 0), Some(0));
+        assert_eq!(admit(Level::Error, "Pathé is down", 30), None);
+        assert_eq!(admit(Level::Error, "Pathé is down", 60), None);
+        assert_eq!(admit(Level::Error, "Pathé is back?", 60), Some(0));
+        assert_eq!(admit(Level::Info, "Checking", 60), Some(0));
+        assert_eq!(admit(Level::Info, "Checking", 61), Some(0));
+
+        assert_eq!(admit(Level::Error, "Pathé is down", 600), Some(2));
+        assert_eq!(admit(Level::Error, "Pathé is down", 601), None);
+    }
+}
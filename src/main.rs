@@ -1,383 +1,454 @@
-extern crate chrono;
-extern crate chrono_tz;
-
 use log::{debug, error, info, trace, warn};
 
-use ctrlc;
-use reqwest::blocking::{Client, Response};
 use serde_json::json;
 use std::{
-    env,
-    fmt::Display,
-    fs::File,
-    io::{BufReader, BufWriter},
-    str::FromStr,
+    fs,
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    vec,
 };
 
+use clap::{Parser, Subcommand};
 use clokwerk::{Scheduler, TimeUnits};
-use scraper::{ElementRef, Html, Selector};
-use serde::{Deserialize, Serialize};
-
-// Defaults
-const CONFIG_FILE: &str = "config.json";
-const DEFAULT_LOG_LEVEL: &str = "Info";
-const DEFAULT_TIMEZONE: &str = "Europe/Amsterdam"; // based on https://docs.rs/chrono-tz/0.5.3/chrono_tz/enum.Tz.html#variants
+use rand::Rng;
 
-// START NOTIFICATIONS
-
-#[derive(Serialize)]
-struct DiscordNotificationField {
-    name: String,
-    value: String,
-    inline: Option<bool>,
+use pathe_monitor::cinema::CinemaRef;
+use pathe_monitor::config::CONFIG_FILE;
+use pathe_monitor::logging::ThrottledLog;
+use pathe_monitor::monitor::{
+    init_config, list_movies, setup_monitor, test_notifiers, validate_config, Monitor,
+};
+use pathe_monitor::settings::{share_settings, validate_env, Config, LogFormat};
+use pathe_monitor::status::setup_http_server;
+
+/// Command line arguments, the long-running behavior is configured through environment variables.
+#[derive(Parser, Debug)]
+#[command(version = pathe_monitor::BUILD_INFO, about)]
+struct Args {
+    /// Check all configured requests once and exit, instead of running the scheduler
+    #[arg(long)]
+    check_once: bool,
+
+    /// Forget which movies have already been notified (or set `RESET_STATE`)
+    #[arg(long)]
+    reset_state: bool,
+
+    /// Log notifications instead of sending them (or set `DRY_RUN`)
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Path of the config file
+    #[arg(long, global = true, env = "CONFIG_PATH", default_value = CONFIG_FILE)]
+    config: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Serialize)]
-struct DiscordNotificationThumbnail {
-    url: String,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print every movie and its showtimes on the schedule, to find out how Pathé titles a movie
+    List {
+        /// One of the known cinema names or the ID of any Pathé cinema
+        #[arg(long)]
+        cinema: CinemaRef,
+
+        /// `DD-MM-YYYY`, `today`, `tomorrow` or `+<days>d`
+        #[arg(long, default_value = "today")]
+        date: String,
+    },
+    /// Ask for requests interactively and add them to the config file
+    Init,
+    /// Send a sample notification through every configured notifier, exits with 1 if any fails
+    TestNotify,
+    /// Check every request in the config file without starting the monitor, exits with 1 if any is invalid
+    Validate,
+    /// Print the version, commit and build date, like `--version`
+    Version,
 }
 
-#[derive(Serialize)]
-struct DiscordNotificationFooter {
-    text: String,
+/// Prints the schedule to stdout, the config file is left untouched.
+async fn list(config: &Config, config_path: &str, cinema: &CinemaRef, date: &str) {
+    match list_movies(config, config_path, cinema, date).await {
+        Ok(movies) if movies.is_empty() => {
+            println!("No movies scheduled in {} on {}", cinema, date)
+        }
+        Ok(movies) => {
+            for movie in movies {
+                match &movie.href {
+                    Some(href) => println!("{} ({})", movie.title, href),
+                    None => println!("{}", movie.title),
+                }
+                for showtime in movie.showtimes {
+                    println!(
+                        "  {} {} - {}{}",
+                        showtime.display_label(),
+                        showtime.start,
+                        showtime.end,
+                        showtime
+                            .availability
+                            .map(|availability| format!(" ({})", availability))
+                            .unwrap_or_default()
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Listing the movies in {} on {} failed: {}", cinema, date, e);
+            std::process::exit(1);
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct DiscordNotificationEmbed {
-    title: String,
-    description: Option<String>,
-    url: String,
-    fields: Vec<DiscordNotificationField>,
-    thumbnail: DiscordNotificationThumbnail,
-    footer: DiscordNotificationFooter,
-}
+/// Prints whether every request is valid, exits with 1 if any isn't.
+async fn validate(config: &Config, config_path: &str) {
+    let results = match validate_config(config, config_path).await {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("`{}` is invalid: {}", config_path, e);
+            std::process::exit(1);
+        }
+    };
 
-#[derive(Serialize)]
-struct DiscordNotification {
-    content: String,
-    embeds: Vec<DiscordNotificationEmbed>,
-}
+    for (request, result) in &results {
+        match result {
+            Ok(()) => println!("OK    {}", request),
+            Err(e) => println!("ERROR {}: {}", request, e),
+        }
+    }
 
-fn notify(client: &reqwest::blocking::Client, notification: DiscordNotification) {
-    let webhook_url = env::var("DISCORD_WEBHOOK_URL").expect("missing `DISCORD_WEBHOOK_URL`-environment variable");
-    info!(
-        "Calling Discord webhook `{}` with payload:\n{}",
-        webhook_url,
-        json!(notification)
+    let invalid = results.iter().filter(|(_, result)| result.is_err()).count();
+    println!(
+        "{} of {} request(s) in `{}` are invalid",
+        invalid,
+        results.len(),
+        config_path
     );
-    let res = client.post(webhook_url).json(&notification).send();
-
-    if res.is_err() {
-        error!("error calling webhook {:?}", res.err());
+    if invalid > 0 {
+        std::process::exit(1);
     }
 }
 
-// END NOTIFICATIONS
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-enum Cinema {
-    Buitenhof = 7,
-    Spuimarkt = 13,
-    Delft = 18,
-}
-
-impl Display for Cinema {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = match self {
-            Cinema::Buitenhof => "Buitenhof",
-            Cinema::Spuimarkt => "Spuimarkt",
-            Cinema::Delft => "Delft",
-        };
-        f.write_str(&format!("Pathé {}", name))
+/// Adds the requests answered on stdin to the config file.
+async fn init(config: &Config, config_path: &str) {
+    let stdin = std::io::stdin();
+    match init_config(
+        config,
+        config_path,
+        &mut stdin.lock(),
+        &mut std::io::stdout(),
+    )
+    .await
+    {
+        Ok(added) => println!("Added {} request(s) to `{}`", added, config_path),
+        Err(e) => {
+            eprintln!("Adding requests to `{}` failed: {}", config_path, e);
+            std::process::exit(1);
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct MovieMonitorRequest {
-    cinema: Cinema,
-    date: String,
-    movie: String,
-}
+/// Prints whether every notifier delivered the sample notification, exits with 1 if any didn't.
+async fn test_notify(config: &Config) {
+    let results = match test_notifiers(config).await {
+        Ok(results) if results.is_empty() => {
+            eprintln!("No notifier configured, set e.g. `DISCORD_WEBHOOK_URL`");
+            std::process::exit(1);
+        }
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Sending a test notification failed: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-impl MovieMonitorRequest {
-    fn api_url(&self) -> String {
-        format!(
-            "https://www.pathe.nl/cinema/schedules?cinemaId={cinema_id}&date={date}",
-            cinema_id = self.cinema.clone() as i32,
-            date = self.date
-        )
+    for (notifier, result) in &results {
+        match result {
+            Ok(()) => println!("OK    {}", notifier),
+            Err(e) => println!("ERROR {}: {}", notifier, e),
+        }
     }
-}
 
-impl Display for MovieMonitorRequest {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!(
-            "'{movie}' op {date} in {cinema}",
-            movie = self.movie,
-            date = self.date,
-            cinema = format!("{}", self.cinema),
-        ))
+    if results.iter().any(|(_, result)| result.is_err()) {
+        std::process::exit(1);
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct MovieMonitorConfig {
-    requests: Vec<MovieMonitorRequest>,
-}
-
-fn generate_notification_field(time: ElementRef) -> DiscordNotificationField {
-    let start_selector = Selector::parse("span.schedule-time__start").unwrap();
-    let end_selector = Selector::parse("span.schedule-time__end").unwrap();
-    let type_selector = Selector::parse("span.schedule-time__label").unwrap();
-
-    let e_start = time.select(&start_selector).next().unwrap();
-    let e_end = time.select(&end_selector).next().unwrap();
-    let e_type = time.select(&type_selector).next().unwrap();
-
-    let start = e_start.text().next().unwrap();
-    let end = e_end.text().next().unwrap();
-    let type_name = e_type.text().next().unwrap();
-
-    let link = format!(
-        "https://pathe.nl{}",
-        time.value().attr("data-href").unwrap()
-    );
-
-    DiscordNotificationField {
-        name: type_name.to_string(),
-        value: format!("[{} - {}]({})", start, end, link),
-        inline: Some(true),
+/// Logs to stdout and, when `log_file` is set, to a file that is rotated daily (`<log_file>.YYYY-MM-DD`).
+/// The noisy dependencies are silenced unless `log_targets` says otherwise, repeated warnings and errors are throttled.
+fn setup_logger(config: &Config, log_level: log::LevelFilter) -> Result<(), fern::InitError> {
+    let log_format = config.log_format;
+    let mut dispatch = fern::Dispatch::new()
+        .format(move |out, message, record| match log_format {
+            LogFormat::Text => out.finish(format_args!(
+                "[{datetime}][{target}][{level}] {message}",
+                datetime = chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                target = record.target(),
+                level = record.level(),
+                message = message
+            )),
+            LogFormat::Json => out.finish(format_args!(
+                "{}",
+                json!({
+                    "timestamp": chrono::Local::now().to_rfc3339(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": message.to_string(),
+                })
+            )),
+        })
+        .level(log_level)
+        .level_for("reqwest::connect", log::LevelFilter::Off)
+        .level_for("html5ever", log::LevelFilter::Off)
+        .level_for("selectors", log::LevelFilter::Off)
+        .level_for("rustls", log::LevelFilter::Off);
+    for (target, level) in &config.log_targets {
+        dispatch = dispatch.level_for(target.clone(), *level);
     }
-}
+    dispatch = dispatch.chain(std::io::stdout());
 
-fn generate_notification(
-    request: MovieMonitorRequest,
-    item: ElementRef,
-) -> Result<DiscordNotification, ()> {
-    trace!("creating notification for {}", request);
-    let MovieMonitorRequest {
-        cinema,
-        date,
-        movie,
-    } = request;
+    if let Some(log_file) = config.log_file.as_deref() {
+        if let Some(parent) = Path::new(log_file).parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        dispatch = dispatch.chain(fern::DateBased::new(format!("{}.", log_file), "%Y-%m-%d"));
+    }
 
-    let title_selector = Selector::parse("h4 a").unwrap();
-    let thumbnail_selector = Selector::parse("div.schedule-simple__poster img").unwrap();
-    let time_selector = Selector::parse("a.schedule-time").unwrap();
+    let (max_level, logger) = dispatch.into_log();
+    log::set_boxed_logger(Box::new(ThrottledLog::new(logger, config.log_throttle)))?;
+    log::set_max_level(max_level);
+    trace!("initialized logger");
 
-    let mut fields = vec![];
+    Ok(())
+}
 
-    let title_element = item.select(&title_selector).next().unwrap();
+/// Set on `SIGUSR1`, the main loop then checks every request right away instead of waiting for the next poll.
+static CHECK_NOW: AtomicBool = AtomicBool::new(false);
 
-    for time in item.select(&time_selector) {
-        let field = generate_notification_field(time);
-        fields.push(field)
+#[cfg(unix)]
+fn setup_check_now_handler() {
+    extern "C" fn check_now(_: libc::c_int) {
+        // storing to an atomic is all that's safe to do in a signal handler
+        CHECK_NOW.store(true, Ordering::SeqCst);
     }
 
-    // fix potential misalignment
-    if fields.len() > 3 && fields.len() % 3 == 2 {
-        fields.push(DiscordNotificationField {
-            name: ":rooster:".to_string(),
-            value: ":popcorn:".to_string(),
-            inline: Some(true),
-        });
+    let handler = check_now as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGUSR1, handler) } == libc::SIG_ERR {
+        warn!("Failed setting the SIGUSR1 handler, checks only run on schedule");
+    } else {
+        info!("Send SIGUSR1 to check every request right away");
     }
+}
 
-    let e_thumbnail = item.select(&thumbnail_selector).next().unwrap();
-    let thumbnail = e_thumbnail.value().attr("src").unwrap();
-
-    let embed = DiscordNotificationEmbed {
-        title: movie.to_string(),
-        description: None,
-        url: format!(
-            "https://pathe.nl{}#agenda",
-            title_element.value().attr("href").unwrap()
-        ),
-        fields,
-        thumbnail: DiscordNotificationThumbnail {
-            url: thumbnail.to_string(),
-        },
-        footer: DiscordNotificationFooter {
-            text: "Generated by *pathe-monitor*".to_string(), // TODO dit dynamischer maken? om het terug te kunnen traceren
-        },
-    };
+#[cfg(not(unix))]
+fn setup_check_now_handler() {
+    debug!("SIGUSR1 isn't available on this platform, checks only run on schedule");
+}
 
-    Ok(DiscordNotification {
-        content: format!(
-            "Er zijn tickets beschikbaar voor '**{movie}**' op **{date}** in **{cinema}**.",
-            movie = movie,
-            date = date,
-            cinema = format!("{}", cinema)
-        ),
-        embeds: vec![embed],
+fn setup_sig_handler(r: Arc<AtomicBool>) {
+    ctrlc::set_handler(move || {
+        trace!("ctrlc-handler called");
+        r.store(false, Ordering::SeqCst);
     })
+    .expect("failed setting Ctrl-C handler");
+    trace!("initialized ctrlc-handler");
 }
 
-fn check_response(
-    request: MovieMonitorRequest,
-    client: &Client,
-    res: Response,
-) -> Result<bool, ()> {
-    debug!("handling {} response", request);
-
-    let fragment = Html::parse_fragment(&res.text().unwrap());
-
-    let scheduled_item_selector = Selector::parse("div.schedule-simple__item").unwrap();
-    let title_selector = Selector::parse("h4 a").unwrap();
-
-    for item in fragment.select(&scheduled_item_selector) {
-        let title_element = item.select(&title_selector).next().unwrap();
-        let title = title_element.text().next().unwrap();
-
-        if title.to_lowercase() == request.movie.to_lowercase() {
-            let notification = generate_notification(request, item).unwrap();
-            notify(&client, notification);
-
-            return Ok(true);
+type SharedMonitor = Arc<tokio::sync::Mutex<Monitor>>;
+
+/// Checks every request on the runtime, unless the previous check is still running.
+fn spawn_check(monitor: SharedMonitor, jitter: std::time::Duration) {
+    tokio::spawn(async move {
+        match monitor.try_lock() {
+            Ok(mut monitor) => {
+                if !jitter.is_zero() {
+                    let delay = rand::thread_rng().gen_range(std::time::Duration::ZERO..=jitter);
+                    info!("Delaying this check by {:?}", delay);
+                    tokio::time::sleep(delay).await;
+                }
+                monitor.check().await;
+            }
+            Err(_) => warn!("The previous check is still running, skipping this one"),
         }
-    }
-
-    Ok(false)
+    });
 }
 
-fn check_pending_movie_request(request: MovieMonitorRequest) -> Result<bool, ()> {
-    info!("Processing {}", request);
+/// Spawns a check onto the runtime every poll interval, skipping it while the previous one is still running.
+/// Every check is delayed by a random part of `poll_jitter`, so not every instance hits Pathé at the same time.
+fn setup_scheduler(
+    config: &Config,
+    tz: chrono_tz::Tz,
+    monitor: SharedMonitor,
+) -> Scheduler<chrono_tz::Tz> {
+    let mut scheduler = Scheduler::with_tz(tz);
+    debug!("initialized scheduler with TZ: '{:?}'", tz);
 
-    let client = reqwest::blocking::Client::new();
-    let res = client.get(request.api_url()).send();
+    // TODO iedere dag een job met welke requests worden gemonitor
 
-    if res.is_err() {
-        error!("error calling {}. {:?}", request.api_url(), res.err());
+    let jitter = config.poll_jitter;
+    if !jitter.is_zero() {
+        info!("Delaying every check by up to {:?}", jitter);
+    }
 
-        return Err(());
+    if config.check_times.is_empty() {
+        let interval = config.poll_interval_minutes;
+        info!("Checking for tickets every {} minutes", interval);
+
+        let monitor = monitor.clone();
+        let job = scheduler
+            .every(interval.minutes())
+            .run(move || spawn_check(monitor.clone(), jitter));
+        debug!("initialized job:\n{:?}", job);
+    } else {
+        info!(
+            "Checking for tickets every day at {}",
+            config
+                .check_times
+                .iter()
+                .map(|time| time.format("%H:%M").to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        for time in &config.check_times {
+            let monitor = monitor.clone();
+            let job = scheduler
+                .every(1.day())
+                .at_time(*time)
+                .run(move || spawn_check(monitor.clone(), jitter));
+            debug!("initialized job:\n{:?}", job);
+        }
     }
 
-    check_response(request, &client, res.unwrap())
+    scheduler
 }
 
-fn read_config_from_file(path: &str) -> Result<MovieMonitorConfig, serde_json::Error> {
-    trace!("reading config from `{}`", path);
-    let file = File::open(path);
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
 
-    if file.is_err() {
-        warn!("`{}` not found, generating a fresh one", path);
-        let config = MovieMonitorConfig { requests: vec![] };
-        write_config_to_file(path, &config);
-
-        return Ok(config);
+    if let Some(Command::Version) = args.command {
+        println!("pathe-monitor {}", pathe_monitor::BUILD_INFO);
+        return;
     }
 
-    let reader = BufReader::new(file.unwrap());
-    serde_json::from_reader(reader)
-}
-
-fn write_config_to_file(path: &str, config: &MovieMonitorConfig) {
-    debug!("writing new config to `{}`", path);
-    let file = File::create(path);
-    let writer = BufWriter::new(file.unwrap());
+    let mut config = match validate_env() {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!(
+                "Pathé monitor is misconfigured:\n - {}",
+                errors.join("\n - ")
+            );
+            std::process::exit(2);
+        }
+    };
 
-    serde_json::to_writer_pretty(writer, &config)
-        .expect(format!("failed writing new `{}`", path).as_str());
-}
+    config.dry_run |= args.dry_run;
+    share_settings(&config);
 
-fn check_pending_movie_requests() {
-    let config = read_config_from_file(CONFIG_FILE).expect("failed reading `config.json`");
+    if let Some(command) = &args.command {
+        // keep stdout for the output of the command
+        setup_logger(&config, config.log_level.min(log::LevelFilter::Warn))
+            .expect("failed to initialize logging");
 
-    info!("Processing {} movie requests", config.requests.len());
-    for request in config.requests {
-        match check_pending_movie_request(request.clone()) {
-            Ok(true) => (),
-            Ok(false) => info!("No tickets available for {}", request),
-            Err(_) => error!("Something went wrong processing {}", request),
-        };
+        match command {
+            Command::List { cinema, date } => list(&config, &args.config, cinema, date).await,
+            Command::Init => init(&config, &args.config).await,
+            Command::TestNotify => test_notify(&config).await,
+            Command::Validate => validate(&config, &args.config).await,
+            Command::Version => unreachable!("printed before reading the environment"),
+        }
+        return;
     }
 
-    // TODO write_config_to_file(CONFIG_FILE, &config);
-}
-
-fn setup_logger(log_level: log::LevelFilter) -> Result<(), fern::InitError> {
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{datetime}][{target}][{level}] {message}",
-                datetime = chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                target = record.target(),
-                level = record.level(),
-                message = message
-            ))
-        })
-        .level(log_level)
-        .level_for("reqwest::connect", log::LevelFilter::Off)
-        .level_for("html5ever", log::LevelFilter::Off)
-        .level_for("selectors", log::LevelFilter::Off)
-        .level_for("rustls", log::LevelFilter::Off)
-        .chain(std::io::stdout())
-        // .chain(fern::log_file("output.log")?)
-        .apply()?;
-    trace!("initialized logger");
-
-    Ok(())
-}
-
-fn setup_sig_handler(r: Arc<AtomicBool>) {
-    ctrlc::set_handler(move || {
-        trace!("ctrlc-handler called");
-        r.store(false, Ordering::SeqCst);
-    })
-    .expect("failed setting Ctrl-C handler");
-    trace!("initialized ctrlc-handler");
-}
+    setup_logger(&config, config.log_level).expect("failed to initialize logging");
 
-fn setup_scheduler() -> Result<Scheduler<chrono_tz::Tz>, String> {
-    let tz: chrono_tz::Tz = env::var("TIMEZONE").unwrap_or(DEFAULT_TIMEZONE.to_string()).parse()?;
-    let mut scheduler = Scheduler::with_tz(tz);
-    debug!("initialized scheduler with TZ: '{:?}'", tz);
     info!(
-        "Time in container is: {:?}",
-        chrono::Local::now().with_timezone(&tz)
+        "Pathé monitor {} is starting up!",
+        pathe_monitor::BUILD_INFO
     );
+    if config.dry_run {
+        warn!("Dry run, notifications are logged instead of sent");
+    }
+    for warning in &config.warnings {
+        warn!("{}", warning);
+    }
 
-    // TODO iedere dag een job met welke requests worden gemonitor
+    let running = Arc::new(AtomicBool::new(true));
 
-    // prepare config-file ahead of time
-    read_config_from_file(CONFIG_FILE).ok();
+    setup_sig_handler(running.clone());
+    setup_check_now_handler();
+
+    // only a config that can't be loaded at startup is fatal, later edits are reloaded or ignored while running
+    let mut monitor = match setup_monitor(&config, &args.config, running.clone()).await {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            error!(
+                "Pathé monitor failed to start with `{}`: {}",
+                args.config, e
+            );
+            std::process::exit(2);
+        }
+    };
+    if args.reset_state || config.reset_state {
+        monitor.reset_state();
+    }
+    let status = monitor.status.clone();
 
-    let job = scheduler
-        .every(30.minutes())
-        .run(check_pending_movie_requests);
-    debug!("initialized job:\n{:?}", job);
+    if args.check_once {
+        let failures = monitor.check().await;
+        info!("Finished checking once, {} request(s) failed", failures);
 
-    Ok(scheduler)
-}
+        std::process::exit(if failures == 0 { 0 } else { 1 });
+    }
 
-fn main() {
-    let log_level =
-        log::LevelFilter::from_str(&env::var("LOG_LEVEL").unwrap_or(DEFAULT_LOG_LEVEL.to_string()))
-            .expect("invalid log level passed");
-    setup_logger(log_level).expect("failed to initialize logging");
+    if config.notify_on_startup {
+        monitor.notify_startup().await;
+    }
 
-    info!("Pathé monitor is starting up!");
+    match (config.health_port, config.metrics_port) {
+        (Some(health_port), Some(metrics_port)) if health_port == metrics_port => {
+            setup_http_server(health_port, Some(status.clone()), true)
+                .expect("failed to start status server");
+        }
+        (health_port, metrics_port) => {
+            if let Some(port) = health_port {
+                setup_http_server(port, Some(status.clone()), false)
+                    .expect("failed to start status server");
+            }
+            if let Some(port) = metrics_port {
+                setup_http_server(port, None, true).expect("failed to start metrics server");
+            }
+        }
+    }
 
-    let running = Arc::new(AtomicBool::new(true));
+    let tz = monitor.tz;
+    let monitor = Arc::new(tokio::sync::Mutex::new(monitor));
+    let mut scheduler = setup_scheduler(&config, tz, monitor.clone());
 
-    setup_sig_handler(running.clone());
-    // TODO validate env variables
-    env::var_os("DISCORD_WEBHOOK_URL").expect("no `DISCORD_WEBHOOK_URL`-environment variable passed");
+    while running.load(Ordering::SeqCst) {
+        status.lock().unwrap().heartbeat();
 
-    let mut scheduler = setup_scheduler().expect("failed to initialize scheduler");
+        if CHECK_NOW.swap(false, Ordering::SeqCst) {
+            info!("Checking every request right away, manually triggered by SIGUSR1");
+            spawn_check(monitor.clone(), std::time::Duration::ZERO);
+        }
 
-    while running.load(Ordering::SeqCst) {
         trace!("run pending jobs");
         scheduler.run_pending();
         trace!("finished pending jobs");
 
-        std::thread::sleep(std::time::Duration::from_millis(500));
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
     }
 
     info!("shutting down");
+    // returning drops the runtime, a check that is still running gets to stop early and save what it notified first
+    if monitor.try_lock().is_err() {
+        info!("Waiting for the running check to finish");
+    }
+    let _monitor = monitor.lock().await;
 }
@@ -0,0 +1,1206 @@
+use log::{debug, error, info, warn};
+
+use reqwest::Client;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{BufRead, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use chrono::{DateTime, NaiveDate, Offset, Utc};
+use futures::{stream, StreamExt};
+
+use crate::api::{fetch_cinema_schedule, FetchedSchedule};
+use crate::cinema::{fetch_cinema_list, validate_cinemas, Cinema, CinemaRef};
+use crate::config::{
+    read_config_from_file, write_config_to_file, ConfigWatcher, MovieMonitorConfig,
+};
+use crate::error::MonitorError;
+use crate::locale::LOCALE;
+use crate::notifications::{
+    notify, notify_text, request_notifiers, setup_notifiers, DiscordNotifier, ErrorReporter,
+    Notification, Notifier, Showtime,
+};
+use crate::request::{resolve_date, MovieMonitorRequest, QuietHours, DATE_FORMAT};
+use crate::scrape::{
+    check_schedule, fetch_with_retries, list_schedule, mark_delivered, not_delivered,
+    skip_in_cooldown, BreakerState, CircuitBreaker, Fetcher, ScheduleCheck, ScheduledMovie,
+    Selectors, PLACEHOLDER_THUMBNAIL_URL,
+};
+use crate::settings::{setup_client, Config};
+use crate::status::{
+    LastRun, MonitorStatus, Outcome, RequestRun, LAST_SUCCESS_TIMESTAMP_SECONDS,
+    REQUEST_DURATION_SECONDS, SCRAPES_TOTAL, SCRAPE_ERRORS_TOTAL, UP,
+};
+use crate::wizard::prompt_requests;
+
+/// Checks every date of the request, returns whether any match is available and the notifications still to send.
+pub(crate) async fn check_pending_movie_request(
+    request: &mut MovieMonitorRequest,
+    selectors: &Selectors,
+    fetcher: &Fetcher,
+    notifiers: &[Arc<dyn Notifier>],
+    dry_run: bool,
+    now: DateTime<chrono_tz::Tz>,
+) -> Result<(bool, Vec<Notification>), MonitorError> {
+    info!("Processing {}", request);
+
+    let today = now.naive_local().date();
+    let notifiers = request_notifiers(request, &fetcher.client, notifiers);
+    if notifiers.is_empty() {
+        return Err(MonitorError::NoNotifier(request.to_string()));
+    }
+    let notifiers = &notifiers[..];
+
+    let started = Instant::now();
+    let mut checked = DatesChecked::default();
+    let dates = check_request_dates(
+        request,
+        selectors,
+        fetcher,
+        notifiers,
+        dry_run,
+        today,
+        &mut checked,
+    );
+    // a stalled request, e.g. on a DNS lookup that never returns, shouldn't hold up the rest of the check
+    let finished = if fetcher.check_timeout.is_zero() {
+        Some(dates.await)
+    } else {
+        tokio::time::timeout(fetcher.check_timeout, dates)
+            .await
+            .ok()
+    };
+    match finished {
+        Some(result) => result?,
+        None => {
+            error!(
+                "Abandoned checking {} after {:?}, the dates checked by then are {}",
+                request,
+                fetcher.check_timeout,
+                match checked.dates.is_empty() {
+                    true => "none".to_string(),
+                    false => checked.dates.join(", "),
+                }
+            );
+            checked.last_error = Some(MonitorError::Timeout(fetcher.check_timeout));
+        }
+    }
+    let result = match checked.last_error {
+        Some(e) if !checked.available => Err(e),
+        _ => {
+            let now = now.with_timezone(&now.offset().fix());
+            Ok((
+                checked.available,
+                skip_in_cooldown(request, checked.pending, now),
+            ))
+        }
+    };
+    let elapsed = started.elapsed();
+    debug!("Checking {} took {:?}", request, elapsed);
+    if !fetcher.slow_request.is_zero() && elapsed > fetcher.slow_request {
+        warn!(
+            "Checking {} took {:?}, longer than {:?}, is Pathé slow?",
+            request, elapsed, fetcher.slow_request
+        );
+    }
+
+    result
+}
+
+/// What checking the dates of a request found so far, which is kept when the check is abandoned.
+#[derive(Default)]
+struct DatesChecked {
+    /// whether any match is available
+    available: bool,
+    /// the notifications still to send
+    pending: Vec<Notification>,
+    /// the dates whose schedules have been checked
+    dates: Vec<String>,
+    last_error: Option<MonitorError>,
+}
+
+/// Fetches and checks the schedules of every date of the request, fails only when the dates can't be resolved.
+async fn check_request_dates(
+    request: &mut MovieMonitorRequest,
+    selectors: &Selectors,
+    fetcher: &Fetcher,
+    notifiers: &[Arc<dyn Notifier>],
+    dry_run: bool,
+    today: NaiveDate,
+    checked: &mut DatesChecked,
+) -> Result<(), MonitorError> {
+    for date in request.date.resolve(today)? {
+        // the schedules of all cinemas on a date are checked together, so their showtimes are compared together
+        let mut schedules = vec![];
+        for cinema in request.cinema.cinemas().to_vec() {
+            SCRAPES_TOTAL.inc();
+            let timer = REQUEST_DURATION_SECONDS.start_timer();
+            let result = fetch_cinema_schedule(
+                &cinema,
+                &date,
+                selectors,
+                fetcher.source,
+                &fetcher.api_url,
+                |url| async move { fetcher.fetch(&url).await },
+            )
+            .await;
+            timer.observe_duration();
+
+            match result {
+                Ok(schedule) => schedules.push((cinema, schedule)),
+                Err(e) => {
+                    SCRAPE_ERRORS_TOTAL
+                        .with_label_values(&[&cinema.to_string()])
+                        .inc();
+                    error!(
+                        "error checking {} on {} in {}: {}",
+                        request, date, cinema, e
+                    );
+                    checked.last_error = Some(e);
+                }
+            }
+        }
+        if schedules.is_empty() {
+            continue;
+        }
+
+        match check_schedule(request, &date, selectors, notifiers, dry_run, &schedules).await {
+            Ok(ScheduleCheck {
+                found,
+                notifications,
+            }) => {
+                LAST_SUCCESS_TIMESTAMP_SECONDS.set(Utc::now().timestamp_millis() as f64 / 1000.0);
+                checked.available |= found;
+                checked.pending.extend(notifications);
+                checked.dates.push(date);
+            }
+            Err(e) => {
+                SCRAPE_ERRORS_TOTAL
+                    .with_label_values(&[&request.cinema.to_string()])
+                    .inc();
+                error!("error checking {} on {}: {}", request, date, e);
+                checked.last_error = Some(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The notifications still to send for the request at this index of the config.
+pub(crate) type Pending = (usize, Vec<Notification>);
+
+/// Groups the pending notifications by the Discord webhook and `on_match_command` of their request, in the order of the
+/// requests.
+pub(crate) fn batch_by_webhook(
+    requests: &[MovieMonitorRequest],
+    mut pending: Vec<Pending>,
+) -> Vec<Vec<Pending>> {
+    pending.sort_by_key(|(index, _)| *index);
+
+    type Targets<'a> = (&'a Option<String>, &'a Option<String>);
+    let mut batches: Vec<(Targets, Vec<Pending>)> = vec![];
+    for (index, notifications) in pending {
+        if notifications.is_empty() {
+            continue;
+        }
+
+        let targets = (
+            &requests[index].webhook_url,
+            &requests[index].on_match_command,
+        );
+        match batches.iter_mut().find(|(other, _)| *other == targets) {
+            Some((_, batch)) => batch.push((index, notifications)),
+            None => batches.push((targets, vec![(index, notifications)])),
+        }
+    }
+
+    batches.into_iter().map(|(_, batch)| batch).collect()
+}
+
+/// Sends the notifications of all requests sharing their notifiers together, e.g. as a single Discord message. Sending
+/// a batch that takes longer than `timeout` is abandoned, zero never abandons.
+pub(crate) async fn notify_batches(
+    requests: &mut [MovieMonitorRequest],
+    client: &Client,
+    notifiers: &[Arc<dyn Notifier>],
+    pending: Vec<Pending>,
+    dry_run: bool,
+    now: DateTime<chrono_tz::Tz>,
+    timeout: std::time::Duration,
+) -> Vec<(usize, CheckError)> {
+    let mut errors = vec![];
+
+    for batch in batch_by_webhook(requests, pending) {
+        let notifications: Vec<Notification> = batch
+            .iter()
+            .flat_map(|(_, notifications)| notifications.iter().cloned())
+            .collect();
+        if batch.len() > 1 {
+            info!(
+                "Sending {} notifications of {} requests together",
+                notifications.len(),
+                batch.len()
+            );
+        }
+
+        let targets = request_notifiers(&requests[batch[0].0], client, notifiers);
+        let sent = notify(&targets, &notifications, dry_run);
+        let delivered = if timeout.is_zero() {
+            Some(sent.await)
+        } else {
+            tokio::time::timeout(timeout, sent).await.ok()
+        };
+        if delivered == Some(true) {
+            for (index, notifications) in &batch {
+                let request = &mut requests[*index];
+                let now = request_now(request, now);
+                mark_delivered(
+                    request,
+                    notifications,
+                    now.with_timezone(&now.offset().fix()),
+                );
+            }
+        } else if !dry_run || delivered.is_none() {
+            for (index, notifications) in &batch {
+                let request = &requests[*index];
+                let e = match delivered {
+                    Some(_) => not_delivered(notifications),
+                    None => MonitorError::Timeout(timeout),
+                };
+                error!("Something went wrong processing {}: {}", request, e);
+                errors.push((
+                    *index,
+                    CheckError {
+                        request_id: Some(request.id()),
+                        message: format!("{}: {}", request, e),
+                    },
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+/// When notifications are held, and whether they are dropped instead of being sent once the window ends.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct QuietHoursPolicy {
+    pub(crate) hours: Option<QuietHours>,
+    pub(crate) drop: bool,
+}
+
+/// Takes the notifications of requests in their quiet hours out of `pending`. Held notifications aren't remembered as
+/// notified, so the first check after the window finds and sends them again, dropped ones are.
+pub(crate) fn hold_in_quiet_hours(
+    requests: &mut [MovieMonitorRequest],
+    pending: Vec<Pending>,
+    policy: QuietHoursPolicy,
+    now: DateTime<chrono_tz::Tz>,
+) -> Vec<Pending> {
+    pending
+        .into_iter()
+        .filter(|(index, notifications)| {
+            let request = &mut requests[*index];
+            let now = request_now(request, now);
+            // validated when loading the config
+            let quiet_hours = match request.quiet_hours(policy.hours) {
+                Ok(Some(quiet_hours)) if quiet_hours.contains(now.time()) => quiet_hours,
+                _ => return true,
+            };
+            if notifications.is_empty() {
+                return true;
+            }
+
+            if policy.drop {
+                info!(
+                    "Quiet hours ({}), dropping {} notification(s) of {}",
+                    quiet_hours,
+                    notifications.len(),
+                    request
+                );
+                // nothing was sent, so a oneshot request stays enabled and no cooldown starts
+                for notification in notifications {
+                    request.mark_seen(notification);
+                }
+            } else {
+                info!(
+                    "Quiet hours ({}), holding {} notification(s) of {} until {}",
+                    quiet_hours,
+                    notifications.len(),
+                    request,
+                    quiet_hours.end.format("%H:%M")
+                );
+            }
+            false
+        })
+        .collect()
+}
+
+/// `now` in the timezone of the request, which has been validated when loading the config.
+fn request_now(
+    request: &MovieMonitorRequest,
+    now: DateTime<chrono_tz::Tz>,
+) -> DateTime<chrono_tz::Tz> {
+    let tz = now.timezone();
+    now.with_timezone(&request.tz(tz).unwrap_or(tz))
+}
+
+/// Why checking a request failed, `request_id` is `None` when no request could be checked at all.
+pub(crate) struct CheckError {
+    pub(crate) request_id: Option<String>,
+    pub(crate) message: String,
+}
+
+/// What a check found for each request, and why it failed.
+pub(crate) struct CheckReport {
+    pub(crate) requests: Vec<RequestRun>,
+    pub(crate) errors: Vec<CheckError>,
+}
+
+/// How many requests a check got to, logged after every check.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct CheckSummary {
+    pub(crate) configured: usize,
+    pub(crate) disabled: usize,
+    /// enabled, but all of their dates have passed
+    pub(crate) past: usize,
+    /// actually checked, the rest was skipped while Pathé kept failing or the monitor shut down
+    pub(crate) processed: usize,
+}
+
+impl Display for CheckSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let enabled = self.configured - self.disabled;
+        write!(
+            f,
+            "{} request(s) configured, {} enabled, {} skipped ({} disabled, {} past, {} not checked), {} processed",
+            self.configured,
+            enabled,
+            self.configured - self.processed,
+            self.disabled,
+            self.past,
+            enabled - self.past - self.processed,
+            self.processed
+        )
+    }
+}
+
+/// The requests with their index in the config, the highest `priority` first and otherwise in the order of the config.
+pub(crate) fn by_priority(
+    requests: &mut [MovieMonitorRequest],
+) -> Vec<(usize, &mut MovieMonitorRequest)> {
+    let mut requests: Vec<_> = requests.iter_mut().enumerate().collect();
+    requests.sort_by_key(|(_, request)| std::cmp::Reverse(request.priority));
+    requests
+}
+
+/// Checks the requests concurrently, in order of their `priority`, at most `max_concurrent_checks` at a time.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn check_pending_movie_requests(
+    config: &mut MovieMonitorConfig,
+    fetcher: &Fetcher,
+    notifiers: &[Arc<dyn Notifier>],
+    dry_run: bool,
+    tz: chrono_tz::Tz,
+    max_concurrent_checks: usize,
+    quiet_hours: QuietHoursPolicy,
+    running: &AtomicBool,
+) -> CheckReport {
+    let now = Utc::now().with_timezone(&tz);
+    *fetcher.last_request.lock().await = None;
+
+    let disabled = config
+        .requests
+        .iter()
+        .filter(|request| !request.enabled)
+        .count();
+    let mut summary = CheckSummary {
+        configured: config.requests.len(),
+        disabled,
+        ..CheckSummary::default()
+    };
+
+    let (state, remaining) = {
+        let breaker = fetcher.breaker.lock().unwrap();
+        (breaker.state(), breaker.remaining())
+    };
+    if let Some(remaining) = remaining {
+        info!(
+            "Pathé keeps failing, skipping this check and backing off for another {}s",
+            remaining.as_secs()
+        );
+        info!("{}", summary);
+        return CheckReport {
+            requests: config
+                .requests
+                .iter()
+                .map(|request| RequestRun::new(request, Outcome::Skipped, None))
+                .collect(),
+            errors: vec![CheckError {
+                request_id: None,
+                message: MonitorError::CircuitOpen(remaining.as_secs()).to_string(),
+            }],
+        };
+    }
+
+    if disabled > 0 {
+        info!("Skipping {} disabled movie request(s)", disabled);
+    }
+
+    info!(
+        "Processing {} movie requests, {} at a time",
+        config.requests.len() - disabled,
+        max_concurrent_checks
+    );
+    let selectors = &config.selectors;
+    let mut checks = vec![];
+    for (index, request) in by_priority(&mut config.requests) {
+        if !request.enabled {
+            debug!("Skipping {}, it is disabled", request);
+            continue;
+        }
+
+        let now = request_now(request, now);
+        if request.date.is_past(now.naive_local().date()) {
+            info!("Skipping {}, its date has passed", request);
+            summary.past += 1;
+            continue;
+        }
+
+        checks.push(async move {
+            if !running.load(Ordering::SeqCst) {
+                info!("Shutting down, skipping {}", request);
+                return (index, Outcome::Skipped, vec![], None);
+            }
+
+            match check_pending_movie_request(request, selectors, fetcher, notifiers, dry_run, now)
+                .await
+            {
+                Ok((true, notifications)) => (index, Outcome::Matched, notifications, None),
+                Ok((false, notifications)) => {
+                    info!("No tickets available for {}", request);
+                    (index, Outcome::NotMatched, notifications, None)
+                }
+                Err(e) => {
+                    error!("Something went wrong processing {}: {}", request, e);
+                    let error = CheckError {
+                        request_id: Some(request.id()),
+                        message: format!("{}: {}", request, e),
+                    };
+                    (index, Outcome::Errored, vec![], Some(error))
+                }
+            }
+        });
+    }
+
+    let results = {
+        let mut checks = checks.into_iter();
+        let mut results = vec![];
+        if state == BreakerState::HalfOpen {
+            if let Some(probe) = checks.next() {
+                info!("Probing Pathé with a single request before resuming all checks");
+                results.push(probe.await);
+            }
+        }
+
+        if fetcher.breaker.lock().unwrap().state() != BreakerState::Open {
+            results.extend(
+                stream::iter(checks)
+                    .buffer_unordered(max_concurrent_checks)
+                    .collect::<Vec<_>>()
+                    .await,
+            );
+        } else {
+            info!("Pathé is still failing, skipping the remaining requests");
+        }
+
+        results
+    };
+
+    summary.processed = results
+        .iter()
+        .filter(|(_, outcome, _, _)| *outcome != Outcome::Skipped)
+        .count();
+    info!("{}", summary);
+
+    // requests that weren't checked stay skipped
+    let mut outcomes = vec![(Outcome::Skipped, None); config.requests.len()];
+    let mut errors = vec![];
+    let mut pending = vec![];
+    for (index, outcome, notifications, error) in results {
+        outcomes[index] = (outcome, error.as_ref().map(|error| error.message.clone()));
+        errors.extend(error);
+        pending.push((index, notifications));
+    }
+    let pending = hold_in_quiet_hours(&mut config.requests, pending, quiet_hours, now);
+
+    for (index, error) in notify_batches(
+        &mut config.requests,
+        &fetcher.client,
+        notifiers,
+        pending,
+        dry_run,
+        now,
+        fetcher.check_timeout,
+    )
+    .await
+    {
+        outcomes[index] = (Outcome::Errored, Some(error.message.clone()));
+        errors.push(error);
+    }
+
+    let requests = config
+        .requests
+        .iter()
+        .zip(outcomes)
+        .map(|(request, (outcome, error))| RequestRun::new(request, outcome, error))
+        .collect();
+
+    if config.auto_prune {
+        config.requests.retain(|request| {
+            let past = request
+                .date
+                .is_past(request_now(request, now).naive_local().date());
+            if past {
+                info!("Pruning {}, its date has passed", request);
+            }
+            !past
+        });
+    }
+
+    CheckReport { requests, errors }
+}
+
+/// Everything needed to check the configured requests.
+pub struct Monitor {
+    pub(crate) watcher: ConfigWatcher,
+    pub(crate) fetcher: Fetcher,
+    pub(crate) notifiers: Vec<Arc<dyn Notifier>>,
+    pub(crate) error_reporter: Option<ErrorReporter>,
+    pub tz: chrono_tz::Tz,
+    pub(crate) max_concurrent_checks: usize,
+    pub(crate) quiet_hours: QuietHoursPolicy,
+    pub status: Arc<Mutex<MonitorStatus>>,
+    pub(crate) running: Arc<AtomicBool>,
+    /// log notifications instead of sending them, and leave the config file alone
+    pub(crate) dry_run: bool,
+    /// where the outcome of every check is written
+    pub(crate) last_run_file: Option<String>,
+}
+
+impl Monitor {
+    /// Lets the notifiers know what is being watched, to confirm the config loaded.
+    pub async fn notify_startup(&self) {
+        let requests: Vec<&MovieMonitorRequest> = self
+            .watcher
+            .config
+            .requests
+            .iter()
+            .filter(|request| request.enabled)
+            .collect();
+        let mut text = LOCALE.startup(requests.len());
+        for request in requests {
+            text.push_str(&format!("\n- {}", request));
+        }
+
+        notify_text(&self.notifiers, &text, self.dry_run).await;
+    }
+
+    /// Forgets what has been notified before, so every match is notified again.
+    pub fn reset_state(&mut self) {
+        self.watcher.reset_state();
+    }
+
+    /// Checks every request once, returns the number of requests that failed.
+    pub async fn check(&mut self) -> usize {
+        self.status.lock().unwrap().heartbeat();
+        self.watcher.reload_if_changed();
+        let before = json!(self.watcher.config);
+        let CheckReport { requests, errors } = check_pending_movie_requests(
+            &mut self.watcher.config,
+            &self.fetcher,
+            &self.notifiers,
+            self.dry_run,
+            self.tz,
+            self.max_concurrent_checks,
+            self.quiet_hours,
+            &self.running,
+        )
+        .await;
+        if self.dry_run {
+            debug!("Dry run, not writing back `{}`", self.watcher.path);
+        } else if json!(self.watcher.config) != before {
+            self.watcher.save();
+        }
+
+        {
+            let mut status = self.status.lock().unwrap();
+            status.heartbeat();
+            status.requests = self.watcher.config.requests.len();
+            status.last_check = Some(Utc::now());
+            status.circuit_breaker = self.fetcher.breaker.lock().unwrap().state();
+            match errors.last() {
+                Some(error) => status.last_error = Some(error.message.clone()),
+                None => status.last_success = status.last_check,
+            }
+            UP.set(if errors.is_empty() { 1 } else { 0 });
+        }
+
+        match &self.last_run_file {
+            Some(path) if self.dry_run => debug!("Dry run, not writing `{}`", path),
+            Some(path) => LastRun {
+                timestamp: Utc::now(),
+                requests,
+                errors: errors.iter().map(|error| error.message.clone()).collect(),
+            }
+            .write(path),
+            None => {}
+        }
+
+        if let Some(reporter) = &mut self.error_reporter {
+            for error in &errors {
+                let key = error.request_id.as_deref().unwrap_or_default();
+                reporter.report(key, &error.message, self.dry_run).await;
+            }
+        }
+
+        errors.len()
+    }
+}
+
+pub async fn setup_monitor(
+    config: &Config,
+    config_path: &str,
+    running: Arc<AtomicBool>,
+) -> Result<Monitor, MonitorError> {
+    let tz = config.timezone;
+    info!(
+        "Time in container is: {:?}",
+        chrono::Local::now().with_timezone(&tz)
+    );
+
+    // prepare config-file ahead of time
+    let watcher = ConfigWatcher::new(config_path)?;
+
+    let client = setup_client(config)?;
+
+    match fetch_cinema_list(&client, &watcher.config.selectors).await {
+        Ok(cinemas) => {
+            info!(
+                "Discovered {} cinemas:\n{}",
+                cinemas.len(),
+                cinemas
+                    .iter()
+                    .map(|cinema| cinema.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+
+            validate_cinemas(&watcher.config, &cinemas);
+        }
+        Err(e) => warn!(
+            "Could not load the cinema list, skipping cinema validation: {}",
+            e
+        ),
+    }
+
+    let notifiers = setup_notifiers(&client, config);
+    info!("Configured {} notifier(s)", notifiers.len());
+
+    let error_reporter = config.error_webhook_url.as_ref().map(|webhook_url| {
+        info!("Reporting failing checks to `ERROR_WEBHOOK_URL`");
+        ErrorReporter {
+            notifier: DiscordNotifier {
+                client: client.clone(),
+                webhook_url: webhook_url.clone(),
+            },
+            reported: HashMap::new(),
+        }
+    });
+
+    let unresolvable: Vec<String> = watcher
+        .config
+        .requests
+        .iter()
+        .filter(|request| request_notifiers(request, &client, &notifiers).is_empty())
+        .map(|request| request.to_string())
+        .collect();
+    if !unresolvable.is_empty() {
+        return Err(MonitorError::NoNotifier(format!(
+            "{}, set `DISCORD_WEBHOOK_URL`, `TELEGRAM_BOT_TOKEN` and `TELEGRAM_CHAT_ID`, `SMTP_HOST` and `EMAIL_TO`, `WEBHOOK_URL` or a `webhook_url` per request",
+            unresolvable.join(", ")
+        )));
+    }
+
+    let max_retries = config.http_max_retries;
+    debug!("retrying failed Pathé requests up to {} times", max_retries);
+    debug!(
+        "waiting at least {:?} between Pathé requests",
+        config.request_delay
+    );
+    let fetcher = Fetcher {
+        client,
+        max_retries,
+        delay: config.request_delay,
+        slow_request: config.slow_request,
+        check_timeout: config.check_timeout,
+        source: config.schedule_source,
+        api_url: config.schedule_api_url.clone(),
+        last_request: tokio::sync::Mutex::new(None),
+        breaker: std::sync::Mutex::new(CircuitBreaker::new(
+            config.circuit_breaker_threshold,
+            config.circuit_breaker_cooldown,
+        )),
+    };
+
+    let status = Arc::new(Mutex::new(MonitorStatus {
+        requests: watcher.config.requests.len(),
+        ..MonitorStatus::default()
+    }));
+
+    Ok(Monitor {
+        watcher,
+        fetcher,
+        notifiers,
+        error_reporter,
+        tz,
+        max_concurrent_checks: config.max_concurrent_checks,
+        quiet_hours: QuietHoursPolicy {
+            hours: config.quiet_hours,
+            drop: config.drop_in_quiet_hours,
+        },
+        status,
+        running,
+        dry_run: config.dry_run,
+        last_run_file: config.last_run_file.clone(),
+    })
+}
+
+/// Validates the config file like `setup_monitor` does, but reports on every request instead of stopping at the first
+/// problem. The cinemas are only checked against Pathé's cinema list when it can be fetched.
+pub async fn validate_config(
+    config: &Config,
+    config_path: &str,
+) -> Result<Vec<(String, Result<(), MonitorError>)>, MonitorError> {
+    // a missing config file shouldn't be generated just for this
+    if !Path::new(config_path).exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("`{}` does not exist", config_path),
+        )
+        .into());
+    }
+    let monitor_config = read_config_from_file(config_path)?;
+    monitor_config.selectors.validate()?;
+
+    let client = setup_client(config)?;
+    let notifiers = setup_notifiers(&client, config);
+    let cinemas = fetch_cinema_list(&client, &monitor_config.selectors)
+        .await
+        .map_err(|e| {
+            warn!(
+                "Could not load the cinema list, skipping cinema validation: {}",
+                e
+            )
+        })
+        .ok();
+
+    let today = Utc::now()
+        .with_timezone(&config.timezone)
+        .naive_local()
+        .date();
+
+    Ok(monitor_config
+        .requests
+        .iter()
+        .map(|request| {
+            let result = request.validate(today).and_then(|_| {
+                if request_notifiers(request, &client, &notifiers).is_empty() {
+                    return Err(MonitorError::NoNotifier(request.to_string()));
+                }
+                match &cinemas {
+                    Some(cinemas) => match request
+                        .cinema
+                        .cinemas()
+                        .iter()
+                        .find(|cinema| !cinema.is_listed(cinemas))
+                    {
+                        Some(cinema) => Err(MonitorError::UnlistedCinema(cinema.id())),
+                        None => Ok(()),
+                    },
+                    None => Ok(()),
+                }
+            });
+            (request.to_string(), result)
+        })
+        .collect())
+}
+
+/// Sends a sample notification, marked as a test, through every configured notifier and returns how each of them did.
+/// The `webhook_url` of a request isn't tried, only the notifiers from the environment.
+pub async fn test_notifiers(
+    config: &Config,
+) -> Result<Vec<(&'static str, Result<(), MonitorError>)>, MonitorError> {
+    let client = setup_client(config)?;
+    let notifiers = setup_notifiers(&client, config);
+    let date = Utc::now()
+        .with_timezone(&config.timezone)
+        .format(DATE_FORMAT)
+        .to_string();
+    let notification = Notification {
+        movie: "Testmelding".to_string(),
+        title: "Testmelding van pathe-monitor".to_string(),
+        date,
+        cinema: CinemaRef::Known(Cinema::Spuimarkt).to_string(),
+        url: "https://www.pathe.nl".to_string(),
+        thumbnail: PLACEHOLDER_THUMBNAIL_URL.to_string(),
+        showtimes: vec![Showtime {
+            label: "Test, dit is geen echte voorstelling".to_string(),
+            start: "19:00".to_string(),
+            end: "21:00".to_string(),
+            starts_at: None,
+            link: "https://www.pathe.nl".to_string(),
+            availability: None,
+            hall: None,
+            preview: false,
+            bookable: true,
+            cinema: None,
+        }],
+        request_id: "test".to_string(),
+        message_template: None,
+    };
+
+    let mut results = vec![];
+    for notifier in notifiers {
+        info!("Sending a test notification through {}", notifier.name());
+        let result = notifier.send(std::slice::from_ref(&notification)).await;
+        results.push((notifier.name(), result));
+    }
+    Ok(results)
+}
+
+/// Asks for requests on `input` and adds them to the config file, returns how many were added.
+pub async fn init_config(
+    config: &Config,
+    config_path: &str,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<usize, MonitorError> {
+    let mut monitor_config = read_config_from_file(config_path)?;
+    monitor_config.selectors.validate()?;
+
+    let client = setup_client(config)?;
+    let cinemas = fetch_cinema_list(&client, &monitor_config.selectors)
+        .await
+        .map_err(|e| warn!("Could not load the cinema list: {}", e))
+        .ok();
+    let today = Utc::now()
+        .with_timezone(&config.timezone)
+        .naive_local()
+        .date();
+
+    let requests = prompt_requests(input, output, cinemas.as_deref(), today)?;
+    let added = requests.len();
+    if added > 0 {
+        monitor_config.requests.extend(requests);
+        write_config_to_file(config_path, &monitor_config)?;
+    }
+
+    Ok(added)
+}
+
+/// Scrapes the schedule of `cinema` on `date`, without matching it against the requests or notifying anyone.
+pub async fn list_movies(
+    config: &Config,
+    config_path: &str,
+    cinema: &CinemaRef,
+    date: &str,
+) -> Result<Vec<ScheduledMovie>, MonitorError> {
+    // only read the selectors, a missing config file shouldn't be generated just for this
+    let selectors = if Path::new(config_path).exists() {
+        read_config_from_file(config_path)?.selectors
+    } else {
+        Selectors::default()
+    };
+    selectors.validate()?;
+
+    let today = Utc::now()
+        .with_timezone(&config.timezone)
+        .naive_local()
+        .date();
+    let date = resolve_date(date, today)?.format(DATE_FORMAT).to_string();
+
+    let client = setup_client(config)?;
+    let client = &client;
+    let schedule = fetch_cinema_schedule(
+        cinema,
+        &date,
+        &selectors,
+        config.schedule_source,
+        &config.schedule_api_url,
+        |url| async move { fetch_with_retries(client, &url, config.http_max_retries).await },
+    )
+    .await?;
+
+    match schedule {
+        FetchedSchedule::Html(body) => list_schedule(&date, &selectors, &body),
+        FetchedSchedule::Json { movies, .. } => Ok(movies),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveTime, TimeZone};
+
+    use crate::api::ScheduleSource;
+    use crate::scrape::scan_schedule;
+    use crate::testing::{request, CountingNotifier, SCHEDULE, SPUIMARKT};
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn check_summary_fits_on_one_line() {
+        let summary = CheckSummary {
+            configured: 7,
+            disabled: 2,
+            past: 1,
+            processed: 3,
+        };
+        assert_eq!(
+            summary.to_string(),
+            "7 request(s) configured, 5 enabled, 4 skipped (2 disabled, 1 past, 1 not checked), 3 processed"
+        );
+    }
+
+    #[test]
+    fn requests_are_checked_by_priority() {
+        let mut requests = vec![request(), request(), request(), request()];
+        requests[1].priority = 10;
+        requests[2].priority = -1;
+        requests[3].priority = 10;
+
+        let order: Vec<usize> = by_priority(&mut requests)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(order, vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn notifications_are_held_or_dropped_in_quiet_hours() {
+        let quiet_hours: QuietHours = "23:00-07:00".parse().unwrap();
+        assert!(quiet_hours.contains(NaiveTime::from_hms(3, 0, 0)));
+        assert!(!quiet_hours.contains(NaiveTime::from_hms(7, 0, 0)));
+        assert!("23:00".parse::<QuietHours>().is_err());
+
+        let mut requests = vec![request(), request(), request()];
+        requests[1].quiet_hours = Some("off".to_string());
+        requests[2].quiet_hours = Some("12:00-13:00".to_string());
+        let pending = || {
+            (0..3)
+                .map(|index| {
+                    let scan = scan_schedule(
+                        &request(),
+                        &SPUIMARKT,
+                        "19-08-2021",
+                        &Selectors::default(),
+                        SCHEDULE,
+                    )
+                    .unwrap()
+                    .unwrap();
+                    (index, scan.notifications)
+                })
+                .collect::<Vec<_>>()
+        };
+        let night = chrono_tz::Europe::Amsterdam
+            .ymd(2021, 8, 19)
+            .and_hms(3, 0, 0);
+        let mut policy = QuietHoursPolicy {
+            hours: Some(quiet_hours),
+            drop: false,
+        };
+
+        let indices = |pending: Vec<Pending>| -> Vec<usize> {
+            pending.into_iter().map(|(index, _)| index).collect()
+        };
+        assert_eq!(
+            indices(hold_in_quiet_hours(&mut requests, pending(), policy, night)),
+            vec![1, 2]
+        );
+        assert!(requests[0].seen_showtimes.is_empty());
+
+        policy.drop = true;
+        assert_eq!(
+            indices(hold_in_quiet_hours(&mut requests, pending(), policy, night)),
+            vec![1, 2]
+        );
+        assert!(!requests[0].seen_showtimes.is_empty());
+    }
+
+    #[test]
+    fn dropped_notifications_leave_oneshot_requests_enabled() {
+        let mut requests = vec![request()];
+        requests[0].oneshot = true;
+        requests[0].cooldown_minutes = Some(60);
+        let notifications = scan_schedule(
+            &requests[0],
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            SCHEDULE,
+        )
+        .unwrap()
+        .unwrap()
+        .notifications;
+        let night = chrono_tz::Europe::Amsterdam
+            .ymd(2021, 8, 19)
+            .and_hms(3, 0, 0);
+        let policy = QuietHoursPolicy {
+            hours: Some("23:00-07:00".parse().unwrap()),
+            drop: true,
+        };
+
+        assert!(
+            hold_in_quiet_hours(&mut requests, vec![(0, notifications)], policy, night).is_empty()
+        );
+        assert!(!requests[0].seen_showtimes.is_empty());
+        assert!(requests[0].enabled);
+        assert_eq!(requests[0].last_notified, None);
+        assert_eq!(
+            requests[0].cooldown_until(night.with_timezone(&night.offset().fix())),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn stalled_requests_are_abandoned() {
+        // accepts connections but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let fetcher = Fetcher {
+            client: Client::new(),
+            max_retries: 0,
+            delay: std::time::Duration::ZERO,
+            slow_request: std::time::Duration::ZERO,
+            check_timeout: std::time::Duration::from_millis(200),
+            source: ScheduleSource::Json,
+            api_url: format!(
+                "http://{}/{{cinema}}/{{date}}",
+                listener.local_addr().unwrap()
+            ),
+            last_request: tokio::sync::Mutex::new(None),
+            breaker: std::sync::Mutex::new(CircuitBreaker::new(0, std::time::Duration::ZERO)),
+        };
+        let notifiers: Vec<Arc<dyn Notifier>> =
+            vec![Arc::new(CountingNotifier(Arc::new(AtomicUsize::new(0))))];
+        let now = chrono_tz::Europe::Amsterdam
+            .ymd(2021, 8, 19)
+            .and_hms(12, 0, 0);
+
+        let result = check_pending_movie_request(
+            &mut request(),
+            &Selectors::default(),
+            &fetcher,
+            &notifiers,
+            false,
+            now,
+        )
+        .await;
+        assert!(matches!(result, Err(MonitorError::Timeout(_))));
+
+        // so is a notification that doesn't go through
+        let mut requests = vec![request()];
+        requests[0].webhook_url =
+            Some(format!("http://{}/webhook", listener.local_addr().unwrap()));
+        let notification = scan_schedule(
+            &requests[0],
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            SCHEDULE,
+        )
+        .unwrap()
+        .unwrap()
+        .notifications;
+        let errors = notify_batches(
+            &mut requests,
+            &fetcher.client,
+            &notifiers,
+            vec![(0, notification)],
+            false,
+            now,
+            fetcher.check_timeout,
+        )
+        .await;
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].1.message.ends_with("abandoned it"));
+        assert!(requests[0].seen_showtimes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn notifications_of_requests_are_batched_per_webhook() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let sent = Arc::new(AtomicUsize::new(0));
+        let notifiers: Vec<Arc<dyn Notifier>> = vec![Arc::new(CountingNotifier(sent.clone()))];
+
+        let mut requests = vec![request(), request(), request()];
+        requests[1].webhook_url = Some(format!("{}/webhook", server.url()));
+        requests[2].oneshot = true;
+        let pending = requests
+            .iter()
+            .enumerate()
+            .map(|(index, request)| {
+                let scan = scan_schedule(
+                    request,
+                    &SPUIMARKT,
+                    "19-08-2021",
+                    &Selectors::default(),
+                    SCHEDULE,
+                )
+                .unwrap()
+                .unwrap();
+                (index, scan.notifications)
+            })
+            .collect::<Vec<_>>();
+
+        let batches = batch_by_webhook(&requests, pending.clone());
+        let indices: Vec<Vec<usize>> = batches
+            .iter()
+            .map(|batch| batch.iter().map(|(index, _)| *index).collect())
+            .collect();
+        assert_eq!(indices, vec![vec![0, 2], vec![1]]);
+
+        let errors = notify_batches(
+            &mut requests,
+            &Client::new(),
+            &notifiers,
+            pending,
+            false,
+            Utc::now().with_timezone(&chrono_tz::Europe::Amsterdam),
+            std::time::Duration::ZERO,
+        )
+        .await;
+        assert!(errors.is_empty());
+        webhook.assert_async().await;
+
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+        assert!(requests
+            .iter()
+            .all(|request| request.last_notified.is_some()));
+        assert!(!requests[2].enabled);
+    }
+}
@@ -0,0 +1,1390 @@
+//! Turns matched movies into messages for the configured backends.
+
+use log::{debug, error, info, warn};
+
+use reqwest::{header::RETRY_AFTER, Client, StatusCode};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, OnceLock},
+    time::Instant,
+};
+
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Serialize;
+
+use crate::error::MonitorError;
+use crate::locale::LOCALE;
+use crate::request::MovieMonitorRequest;
+use crate::settings::Config;
+use crate::status::NOTIFICATIONS_SENT_TOTAL;
+
+pub(crate) const DEFAULT_WEBHOOK_TEMPLATE: &str = r#"{"movie": "{movie}", "title": "{title}", "date": "{date}", "cinema": "{cinema}", "url": "{url}", "showtimes": "{showtimes}"}"#;
+
+/// A single bookable showing of a movie, as scraped from the schedule.
+#[derive(Clone, Debug)]
+pub struct Showtime {
+    pub label: String,
+    /// the start as displayed on the schedule, e.g. `19:00`
+    pub start: String,
+    pub end: String,
+    /// the start on the requested date, `None` when it couldn't be parsed
+    pub starts_at: Option<DateTime<Tz>>,
+    pub link: String,
+    /// e.g. `bijna uitverkocht`, when the schedule says so
+    pub availability: Option<String>,
+    /// e.g. `Zaal 6` or `IMAX`, when the schedule says so
+    pub hall: Option<String>,
+    /// a preview or member-only (Pathé Unlimited) screening
+    pub preview: bool,
+    /// whether tickets are on sale, a showing can be listed before the pre-sale opens or after it sold out
+    pub bookable: bool,
+    /// the cinema of the showing, when a request watches several cinemas
+    pub cinema: Option<String>,
+}
+
+impl Showtime {
+    /// The label to show in a notification, marking previews and naming the hall and the cinema when there are several.
+    pub fn display_label(&self) -> String {
+        let mut label = self.label.clone();
+        if self.preview {
+            label.push_str(&format!(" ({})", LOCALE.preview()));
+        }
+        if let Some(hall) = &self.hall {
+            label.push_str(&format!(", {}", hall));
+        }
+        if let Some(cinema) = &self.cinema {
+            label.push_str(&format!(" in {}", cinema));
+        }
+        label
+    }
+
+    /// The start and end, linking to the tickets in the way `format` renders links.
+    pub(crate) fn link_text(&self, format: TextFormat) -> String {
+        match format {
+            TextFormat::Markdown => format!("[{} - {}]({})", self.start, self.end, self.link),
+            TextFormat::Html => format!(
+                "<a href=\"{}\">{} - {}</a>",
+                escape_html(&self.link),
+                escape_html(&self.start),
+                escape_html(&self.end)
+            ),
+            TextFormat::Plain => format!("{} - {}: {}", self.start, self.end, self.link),
+        }
+    }
+
+    /// The availability to show next to the showtime, if it is known.
+    pub(crate) fn availability_note(&self) -> String {
+        match &self.availability {
+            Some(availability) => format!(" ⚠️ {}", availability),
+            None => String::new(),
+        }
+    }
+}
+
+/// How a backend renders links, a plain text channel shows the URL itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextFormat {
+    Markdown,
+    Html,
+    Plain,
+}
+
+/// Backend-agnostic description of a movie with available tickets.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub movie: String,
+    /// title of the scheduled movie that matched
+    pub title: String,
+    pub date: String,
+    pub cinema: String,
+    pub url: String,
+    pub thumbnail: String,
+    pub showtimes: Vec<Showtime>,
+    /// `MovieMonitorRequest::id` of the request that matched
+    pub request_id: String,
+    /// `message_template` of the request that matched
+    pub message_template: Option<String>,
+}
+
+/// `Config::message_template`, set by `share_settings` at startup.
+pub(crate) static MESSAGE_TEMPLATE: OnceLock<Option<String>> = OnceLock::new();
+/// `Config::instance_name`, set by `share_settings` at startup.
+pub(crate) static INSTANCE_NAME: OnceLock<Option<String>> = OnceLock::new();
+
+/// The name of this monitor in notifications, if it has one.
+pub(crate) fn instance_name() -> Option<&'static str> {
+    INSTANCE_NAME.get()?.as_deref()
+}
+
+impl Notification {
+    /// Identifies the request and the monitor instance that sent the notification.
+    pub(crate) fn source(&self) -> String {
+        let mut source = format!("{} | {} | #{}", self.cinema, self.date, self.request_id);
+        if let Some(instance) = instance_name() {
+            source.push_str(&format!(" | {}", instance));
+        }
+        source
+    }
+
+    /// Fills in the `{movie}`, `{date}`, `{weekday}`, `{cinema}` and `{count}` placeholders of the message template,
+    /// `escape` formats the text for the backend and `emphasize` the movie, date and cinema.
+    pub(crate) fn message(
+        &self,
+        escape: impl Fn(&str) -> String,
+        emphasize: impl Fn(&str) -> String,
+    ) -> String {
+        let template = self
+            .message_template
+            .as_deref()
+            .or(MESSAGE_TEMPLATE.get().and_then(Option::as_deref))
+            .unwrap_or_else(|| LOCALE.message_template());
+
+        escape(template)
+            .replace("{movie}", &emphasize(&self.movie))
+            .replace("{date}", &emphasize(&self.date))
+            .replace("{weekday}", &escape(&LOCALE.weekday(&self.date)))
+            .replace("{cinema}", &emphasize(&self.cinema))
+            .replace("{count}", &escape(&self.showtime_count()))
+    }
+
+    /// How many showtimes passed the filters, e.g. `3 voorstellingen beschikbaar`.
+    pub(crate) fn showtime_count(&self) -> String {
+        LOCALE.showtime_count(self.showtimes.len())
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Name of the backend, used in logging.
+    fn name(&self) -> &'static str;
+
+    /// Sends the notifications together, as a single message where the backend allows it.
+    async fn send(&self, msgs: &[Notification]) -> Result<(), MonitorError>;
+
+    /// The payload(s) `send` would post, for logging.
+    fn preview(&self, msgs: &[Notification]) -> String;
+
+    /// How the links to the showtimes are written in messages of this backend.
+    fn format(&self) -> TextFormat {
+        TextFormat::Plain
+    }
+
+    /// Sends a message that isn't about a movie, like the startup summary.
+    async fn send_text(&self, text: &str) -> Result<(), MonitorError> {
+        debug!(
+            "{} doesn't support plain messages, skipping:\n{}",
+            self.name(),
+            text
+        );
+        Ok(())
+    }
+}
+
+/// Sends a plain message through every notifier, returns whether at least one of them succeeded.
+///
+/// On a dry run the message is only logged and nothing counts as delivered.
+pub(crate) async fn notify_text(
+    notifiers: &[Arc<dyn Notifier>],
+    text: &str,
+    dry_run: bool,
+) -> bool {
+    let mut delivered = false;
+
+    for notifier in notifiers {
+        if dry_run {
+            info!(
+                "Dry run, not sending {} message:\n{}",
+                notifier.name(),
+                text
+            );
+            continue;
+        }
+
+        match notifier.send_text(text).await {
+            Ok(()) => delivered = true,
+            Err(e) => error!("error sending {} message: {}", notifier.name(), e),
+        }
+    }
+
+    delivered
+}
+
+/// Sends the notifications through every notifier, returns whether at least one of them succeeded.
+///
+/// On a dry run the notifications are only logged and nothing counts as delivered.
+pub(crate) async fn notify(
+    notifiers: &[Arc<dyn Notifier>],
+    notifications: &[Notification],
+    dry_run: bool,
+) -> bool {
+    if dry_run {
+        for notifier in notifiers {
+            info!(
+                "Dry run, not sending {} notification:\n{}",
+                notifier.name(),
+                notifier.preview(notifications)
+            );
+        }
+        return false;
+    }
+
+    let mut delivered = false;
+
+    for notifier in notifiers {
+        match notifier.send(notifications).await {
+            Ok(()) => {
+                NOTIFICATIONS_SENT_TOTAL.inc();
+                delivered = true;
+            }
+            Err(e) => error!(
+                "error sending {} notification for {}: {}",
+                notifier.name(),
+                notifications
+                    .iter()
+                    .map(|notification| format!("'{}'", notification.title))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                e
+            ),
+        }
+    }
+
+    delivered
+}
+
+/// Builds a notifier for every configured backend.
+pub(crate) fn setup_notifiers(client: &Client, config: &Config) -> Vec<Arc<dyn Notifier>> {
+    let mut notifiers: Vec<Arc<dyn Notifier>> = vec![];
+
+    if let Some(webhook_url) = &config.discord_webhook_url {
+        notifiers.push(Arc::new(DiscordNotifier {
+            client: client.clone(),
+            webhook_url: webhook_url.clone(),
+        }));
+    }
+
+    if let Some(telegram) = &config.telegram {
+        notifiers.push(Arc::new(TelegramNotifier {
+            client: client.clone(),
+            bot_token: telegram.bot_token.clone(),
+            chat_id: telegram.chat_id.clone(),
+        }));
+    }
+
+    if let Some(webhook) = &config.generic_webhook {
+        notifiers.push(Arc::new(GenericWebhookNotifier {
+            client: client.clone(),
+            url: webhook.url.clone(),
+            template: webhook.template.clone(),
+        }));
+    }
+
+    if let Some(email) = &config.email {
+        match email.transport(config.http_timeout) {
+            Ok(transport) => notifiers.push(Arc::new(EmailNotifier {
+                transport,
+                from: email.from.clone(),
+                to: email.to.clone(),
+            })),
+            Err(e) => error!(
+                "failed setting up email notifications, skipping them: {}",
+                e
+            ),
+        }
+    }
+
+    notifiers
+}
+
+/// The notifiers of a request, its own `webhook_url` replaces the global Discord webhook.
+pub(crate) fn request_notifiers(
+    request: &MovieMonitorRequest,
+    client: &Client,
+    notifiers: &[Arc<dyn Notifier>],
+) -> Vec<Arc<dyn Notifier>> {
+    let mut notifiers: Vec<Arc<dyn Notifier>> = match &request.webhook_url {
+        Some(webhook_url) => notifiers
+            .iter()
+            .filter(|notifier| notifier.name() != DiscordNotifier::NAME)
+            .cloned()
+            .chain(std::iter::once(Arc::new(DiscordNotifier {
+                client: client.clone(),
+                webhook_url: webhook_url.clone(),
+            }) as Arc<dyn Notifier>))
+            .collect(),
+        None => notifiers.to_vec(),
+    };
+    if let Some(command) = &request.on_match_command {
+        notifiers.push(Arc::new(CommandNotifier {
+            command: command.clone(),
+        }));
+    }
+
+    notifiers
+}
+
+/// Posts failed checks to `ERROR_WEBHOOK_URL`, at most once per `INTERVAL` for the same request.
+pub(crate) struct ErrorReporter {
+    pub(crate) notifier: DiscordNotifier,
+    /// when an error was last reported, by request ID
+    pub(crate) reported: HashMap<String, Instant>,
+}
+
+impl ErrorReporter {
+    pub(crate) const INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+    /// Whether an error about `key` may be reported at `now`, remembers that it is when it may.
+    pub(crate) fn should_report(&mut self, key: &str, now: Instant) -> bool {
+        match self.reported.get(key) {
+            Some(reported) if now.duration_since(*reported) < Self::INTERVAL => false,
+            _ => {
+                self.reported.insert(key.to_string(), now);
+                true
+            }
+        }
+    }
+
+    pub(crate) async fn report(&mut self, key: &str, message: &str, dry_run: bool) {
+        if !self.should_report(key, Instant::now()) {
+            debug!(
+                "Already reported an error within the last {:?}, not reporting: {}",
+                Self::INTERVAL,
+                message
+            );
+            return;
+        }
+
+        let mut text = LOCALE.check_failed(message);
+        if let Some(instance) = instance_name() {
+            text.push_str(&format!(" ({})", instance));
+        }
+        if dry_run {
+            info!("Dry run, not reporting error:\n{}", text);
+            return;
+        }
+
+        if let Err(e) = self.notifier.send_text(&text).await {
+            error!("error reporting to `ERROR_WEBHOOK_URL`: {}", e);
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiscordNotificationField {
+    pub(crate) name: String,
+    pub(crate) value: String,
+    pub(crate) inline: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiscordNotificationThumbnail {
+    pub(crate) url: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiscordNotificationFooter {
+    pub(crate) text: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiscordNotificationEmbed {
+    pub(crate) title: String,
+    pub(crate) description: Option<String>,
+    pub(crate) url: String,
+    pub(crate) fields: Vec<DiscordNotificationField>,
+    pub(crate) thumbnail: DiscordNotificationThumbnail,
+    pub(crate) footer: DiscordNotificationFooter,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiscordNotification {
+    pub(crate) content: String,
+    pub(crate) embeds: Vec<DiscordNotificationEmbed>,
+}
+
+impl DiscordNotificationEmbed {
+    /// The embed about the notification, with its showtimes linked in `format`.
+    pub(crate) fn new(notification: &Notification, format: TextFormat) -> Self {
+        let fields: Vec<DiscordNotificationField> = notification
+            .showtimes
+            .iter()
+            .map(|showtime| DiscordNotificationField {
+                name: truncate(&showtime.display_label(), DiscordNotifier::MAX_FIELD_NAME),
+                value: truncate(
+                    &format!(
+                        "{}{}",
+                        showtime.link_text(format),
+                        showtime.availability_note()
+                    ),
+                    DiscordNotifier::MAX_FIELD_VALUE,
+                ),
+                inline: Some(true),
+            })
+            .collect();
+
+        DiscordNotificationEmbed {
+            title: truncate(&notification.title, DiscordNotifier::MAX_TITLE),
+            description: None,
+            url: notification.url.clone(),
+            fields,
+            thumbnail: DiscordNotificationThumbnail {
+                url: notification.thumbnail.clone(),
+            },
+            footer: DiscordNotificationFooter {
+                text: truncate(
+                    &format!(
+                        "Generated by pathe-monitor {} ({}) | {}",
+                        env!("CARGO_PKG_VERSION"),
+                        env!("GIT_COMMIT"),
+                        notification.source()
+                    ),
+                    DiscordNotifier::MAX_FOOTER,
+                ),
+            },
+        }
+    }
+}
+
+/// Shortens `text` to at most `max` characters, ending in an ellipsis when anything was cut.
+pub(crate) fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+impl DiscordNotificationField {
+    pub(crate) fn length(&self) -> usize {
+        self.name.chars().count() + self.value.chars().count()
+    }
+}
+
+impl DiscordNotificationEmbed {
+    /// The characters Discord counts towards the limit of a message.
+    pub(crate) fn length(&self) -> usize {
+        self.title.chars().count()
+            + self
+                .description
+                .as_deref()
+                .unwrap_or_default()
+                .chars()
+                .count()
+            + self.footer.text.chars().count()
+            + self
+                .fields
+                .iter()
+                .map(DiscordNotificationField::length)
+                .sum::<usize>()
+    }
+
+    /// Splits the showtimes over as many embeds as Discord needs, numbering their titles when there are several.
+    pub(crate) fn split(mut self, pad: bool) -> Vec<Self> {
+        // whole rows of three, so a padded embed doesn't exceed the limit either
+        let fields_per_embed = DiscordNotifier::MAX_FIELDS - DiscordNotifier::MAX_FIELDS % 3;
+        let empty = self.length()
+            - self
+                .fields
+                .iter()
+                .map(DiscordNotificationField::length)
+                .sum::<usize>();
+
+        let mut parts: Vec<Vec<DiscordNotificationField>> = vec![vec![]];
+        let mut length = empty;
+        for field in std::mem::take(&mut self.fields) {
+            let part = parts.last_mut().unwrap();
+            if !part.is_empty()
+                && (part.len() == fields_per_embed
+                    || length + field.length() > DiscordNotifier::MAX_CHARACTERS)
+            {
+                parts.push(vec![]);
+                length = empty;
+            }
+            length += field.length();
+            parts.last_mut().unwrap().push(field);
+        }
+
+        let count = parts.len();
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, fields)| {
+                let title = match count {
+                    1 => self.title.clone(),
+                    _ => truncate(
+                        &format!("{} ({}/{})", self.title, i + 1, count),
+                        DiscordNotifier::MAX_TITLE,
+                    ),
+                };
+                let mut embed = DiscordNotificationEmbed {
+                    title,
+                    description: self.description.clone(),
+                    url: self.url.clone(),
+                    fields,
+                    thumbnail: DiscordNotificationThumbnail {
+                        url: self.thumbnail.url.clone(),
+                    },
+                    footer: DiscordNotificationFooter {
+                        text: self.footer.text.clone(),
+                    },
+                };
+                if pad {
+                    embed.pad_alignment();
+                }
+                embed
+            })
+            .collect()
+    }
+
+    /// Fills the last row of showtimes, Discord shows three inline fields per row and stretches a last row of two.
+    pub(crate) fn pad_alignment(&mut self) {
+        if self.fields.len() > 3 && self.fields.len() % 3 == 2 {
+            self.fields.push(DiscordNotificationField {
+                name: ":rooster:".to_string(),
+                value: ":popcorn:".to_string(),
+                inline: Some(true),
+            });
+        }
+    }
+}
+
+/// `Config::discord_pad_alignment`, set by `share_settings` at startup.
+pub(crate) static PAD_ALIGNMENT: OnceLock<bool> = OnceLock::new();
+
+/// Whether Discord embeds are padded to full rows of showtimes, they are unless `DISCORD_PAD_ALIGNMENT` is off.
+pub(crate) fn pad_alignment() -> bool {
+    PAD_ALIGNMENT.get().copied().unwrap_or(true)
+}
+
+impl DiscordNotification {
+    /// The messages about the notifications, as few as Discord's limits on the number of embeds and characters allow.
+    /// The embeds are padded with `pad_alignment` when `pad` is set.
+    pub(crate) fn messages(
+        notifications: &[Notification],
+        pad: bool,
+        format: TextFormat,
+    ) -> Vec<Self> {
+        let mut messages: Vec<DiscordNotification> = vec![];
+
+        for notification in notifications {
+            let line = truncate(
+                &notification.message(str::to_string, |value| format!("**{}**", value)),
+                DiscordNotifier::MAX_CONTENT,
+            );
+
+            for (i, embed) in DiscordNotificationEmbed::new(notification, format)
+                .split(pad)
+                .into_iter()
+                .enumerate()
+            {
+                let fits = messages.last().is_some_and(|message| {
+                    message.embeds.len() < DiscordNotifier::MAX_EMBEDS
+                        && message.length() + embed.length() <= DiscordNotifier::MAX_CHARACTERS
+                        && (i > 0
+                            || message.content.chars().count() + 1 + line.chars().count()
+                                <= DiscordNotifier::MAX_CONTENT)
+                });
+                if !fits {
+                    messages.push(DiscordNotification {
+                        content: String::new(),
+                        embeds: vec![],
+                    });
+                }
+
+                let message = messages.last_mut().unwrap();
+                if i == 0 {
+                    if !message.content.is_empty() {
+                        message.content.push('\n');
+                    }
+                    message.content.push_str(&line);
+                }
+                message.embeds.push(embed);
+            }
+        }
+
+        messages
+    }
+
+    /// The characters of the embeds, which Discord limits per message.
+    pub(crate) fn length(&self) -> usize {
+        self.embeds
+            .iter()
+            .map(DiscordNotificationEmbed::length)
+            .sum()
+    }
+}
+
+pub(crate) struct DiscordNotifier {
+    pub(crate) client: Client,
+    pub(crate) webhook_url: String,
+}
+
+impl DiscordNotifier {
+    pub(crate) const NAME: &'static str = "Discord";
+    /// Discord rejects messages with more embeds than this.
+    pub(crate) const MAX_EMBEDS: usize = 10;
+    /// Discord rejects embeds with more fields than this.
+    pub(crate) const MAX_FIELDS: usize = 25;
+    /// Discord rejects messages whose embeds have more characters than this in total.
+    pub(crate) const MAX_CHARACTERS: usize = 6000;
+    pub(crate) const MAX_CONTENT: usize = 2000;
+    pub(crate) const MAX_TITLE: usize = 256;
+    pub(crate) const MAX_FIELD_NAME: usize = 256;
+    pub(crate) const MAX_FIELD_VALUE: usize = 1024;
+    pub(crate) const MAX_FOOTER: usize = 2048;
+    /// How often a rate limited message is resent before giving up.
+    pub(crate) const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+    /// Posts to the webhook, waiting out Discord's rate limit when it responds with `429 Too Many Requests`.
+    pub(crate) async fn post(&self, payload: &serde_json::Value) -> Result<(), MonitorError> {
+        let mut attempt = 0;
+
+        loop {
+            let response = self
+                .client
+                .post(&self.webhook_url)
+                .json(payload)
+                .send()
+                .await?;
+            let status = response.status();
+            if status.is_success() {
+                return Ok(());
+            }
+
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|retry_after| retry_after.to_str().ok())
+                .and_then(|retry_after| retry_after.parse::<f64>().ok());
+            let body = response.text().await.unwrap_or_default();
+            // Discord also puts the seconds to wait in the body
+            let retry_after = retry_after.or_else(|| {
+                serde_json::from_str::<serde_json::Value>(&body)
+                    .ok()
+                    .and_then(|body| body["retry_after"].as_f64())
+            });
+
+            match retry_after {
+                Some(retry_after)
+                    if status == StatusCode::TOO_MANY_REQUESTS
+                        && attempt < Self::MAX_RATE_LIMIT_RETRIES =>
+                {
+                    attempt += 1;
+                    warn!(
+                        "Discord is rate limiting `{}`, resending in {}s, retry {}/{}",
+                        self.webhook_url,
+                        retry_after,
+                        attempt,
+                        Self::MAX_RATE_LIMIT_RETRIES
+                    );
+                    tokio::time::sleep(std::time::Duration::from_secs_f64(retry_after.max(0.0)))
+                        .await;
+                }
+                _ => {
+                    return Err(MonitorError::Webhook(format!(
+                        "Discord responded with {}: {}",
+                        status, body
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn format(&self) -> TextFormat {
+        TextFormat::Markdown
+    }
+
+    async fn send(&self, msgs: &[Notification]) -> Result<(), MonitorError> {
+        for notification in DiscordNotification::messages(msgs, pad_alignment(), self.format()) {
+            let payload = json!(notification);
+            info!(
+                "Calling Discord webhook `{}` with payload:\n{}",
+                self.webhook_url, payload
+            );
+            self.post(&payload).await?;
+        }
+
+        Ok(())
+    }
+
+    fn preview(&self, msgs: &[Notification]) -> String {
+        DiscordNotification::messages(msgs, pad_alignment(), self.format())
+            .iter()
+            .map(|notification| json!(notification).to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    async fn send_text(&self, text: &str) -> Result<(), MonitorError> {
+        info!(
+            "Calling Discord webhook `{}` with:\n{}",
+            self.webhook_url, text
+        );
+        self.post(&json!({ "content": text })).await
+    }
+}
+
+pub(crate) struct TelegramNotifier {
+    pub(crate) client: Client,
+    pub(crate) bot_token: String,
+    pub(crate) chat_id: String,
+}
+
+/// Escapes the characters that have a meaning in Telegram's (legacy) `Markdown` parse mode.
+pub(crate) fn escape_telegram_markdown(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| match c {
+            '_' | '*' | '`' | '[' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+/// The message about a single notification, in Telegram's `Markdown` with the showtimes linked in `format`.
+pub(crate) fn telegram_text(msg: &Notification, format: TextFormat) -> String {
+    let mut text = format!(
+        "{message}\n[{title}]({url})\n",
+        message = msg.message(escape_telegram_markdown, |value| format!(
+            "*{}*",
+            escape_telegram_markdown(value)
+        )),
+        title = escape_telegram_markdown(&msg.title),
+        url = msg.url
+    );
+    for showtime in &msg.showtimes {
+        text.push_str(&format!(
+            "\n{label}: {link}{availability}",
+            label = escape_telegram_markdown(&showtime.display_label()),
+            link = showtime.link_text(format),
+            availability = escape_telegram_markdown(&showtime.availability_note())
+        ));
+    }
+
+    text
+}
+
+impl TelegramNotifier {
+    pub(crate) fn payload(&self, msgs: &[Notification]) -> serde_json::Value {
+        let text = msgs
+            .iter()
+            .map(|msg| telegram_text(msg, self.format()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        json!({
+            "chat_id": self.chat_id,
+            "text": text,
+            "parse_mode": "Markdown",
+            "disable_web_page_preview": true,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    fn format(&self) -> TextFormat {
+        TextFormat::Markdown
+    }
+
+    async fn send(&self, msgs: &[Notification]) -> Result<(), MonitorError> {
+        let payload = self.payload(msgs);
+        info!(
+            "Calling Telegram sendMessage for chat `{}` with payload:\n{}",
+            self.chat_id, payload
+        );
+        self.client
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                self.bot_token
+            ))
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    fn preview(&self, msgs: &[Notification]) -> String {
+        self.payload(msgs).to_string()
+    }
+
+    async fn send_text(&self, text: &str) -> Result<(), MonitorError> {
+        info!(
+            "Calling Telegram sendMessage for chat `{}` with:\n{}",
+            self.chat_id, text
+        );
+        self.client
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                self.bot_token
+            ))
+            .json(&json!({ "chat_id": self.chat_id, "text": text }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// Posts a user-supplied JSON template, for services without a dedicated notifier.
+pub(crate) struct GenericWebhookNotifier {
+    pub(crate) client: Client,
+    pub(crate) url: String,
+    pub(crate) template: String,
+}
+
+/// Fills in the `{movie}`, `{title}`, `{date}`, `{cinema}`, `{url}` and `{showtimes}` placeholders of the template.
+///
+/// The values are escaped as JSON strings, so the placeholders belong inside quotes. The showtimes are linked in
+/// `format`.
+pub(crate) fn render_webhook_template(
+    template: &str,
+    msg: &Notification,
+    format: TextFormat,
+) -> Result<serde_json::Value, serde_json::Error> {
+    let escape = |value: &str| {
+        let quoted = json!(value).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let showtimes = msg
+        .showtimes
+        .iter()
+        .map(|showtime| {
+            format!(
+                "{} {}{}",
+                showtime.display_label(),
+                showtime.link_text(format),
+                showtime.availability_note()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let rendered = template
+        .replace("{movie}", &escape(&msg.movie))
+        .replace("{title}", &escape(&msg.title))
+        .replace("{date}", &escape(&msg.date))
+        .replace("{cinema}", &escape(&msg.cinema))
+        .replace("{url}", &escape(&msg.url))
+        .replace("{showtimes}", &escape(&showtimes));
+
+    serde_json::from_str(&rendered)
+}
+
+#[async_trait]
+impl Notifier for GenericWebhookNotifier {
+    fn name(&self) -> &'static str {
+        "Webhook"
+    }
+
+    async fn send(&self, msgs: &[Notification]) -> Result<(), MonitorError> {
+        for msg in msgs {
+            let payload = render_webhook_template(&self.template, msg, self.format())?;
+            info!("Calling webhook `{}` with payload:\n{}", self.url, payload);
+            self.client
+                .post(&self.url)
+                .json(&payload)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        Ok(())
+    }
+
+    fn preview(&self, msgs: &[Notification]) -> String {
+        msgs.iter()
+            .map(
+                |msg| match render_webhook_template(&self.template, msg, self.format()) {
+                    Ok(payload) => payload.to_string(),
+                    Err(e) => format!("invalid template: {}", e),
+                },
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Runs the `on_match_command` of a request for every notification.
+pub(crate) struct CommandNotifier {
+    pub(crate) command: String,
+}
+
+impl CommandNotifier {
+    /// The environment variables the command can read the notification from, with the showtimes linked in `format`.
+    pub(crate) fn env(msg: &Notification, format: TextFormat) -> Vec<(&'static str, String)> {
+        vec![
+            ("PATHE_MOVIE", msg.movie.clone()),
+            ("PATHE_TITLE", msg.title.clone()),
+            ("PATHE_DATE", msg.date.clone()),
+            ("PATHE_CINEMA", msg.cinema.clone()),
+            ("PATHE_URL", msg.url.clone()),
+            (
+                "PATHE_SHOWTIMES",
+                msg.showtimes
+                    .iter()
+                    .map(|showtime| {
+                        format!(
+                            "{} {}",
+                            showtime.display_label(),
+                            showtime.link_text(format)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            ("PATHE_REQUEST_ID", msg.request_id.clone()),
+        ]
+    }
+
+    pub(crate) fn run(command: &str, env: Vec<(&'static str, String)>) -> Result<(), MonitorError> {
+        let mut process = if cfg!(windows) {
+            let mut process = std::process::Command::new("cmd");
+            process.arg("/C");
+            process
+        } else {
+            let mut process = std::process::Command::new("sh");
+            process.arg("-c");
+            process
+        };
+        let output = process
+            .arg(command)
+            .envs(env)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .map_err(|e| {
+                MonitorError::Command(format!("`{}` could not be started: {}", command, e))
+            })?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stdout.trim().is_empty() {
+            debug!("`{}` wrote:\n{}", command, stdout.trim_end());
+        }
+        if !output.status.success() {
+            return Err(MonitorError::Command(format!(
+                "`{}` exited with {}: {}",
+                command,
+                output.status,
+                stderr.trim()
+            )));
+        }
+        if !stderr.trim().is_empty() {
+            warn!("`{}` wrote to stderr:\n{}", command, stderr.trim_end());
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for CommandNotifier {
+    fn name(&self) -> &'static str {
+        "Command"
+    }
+
+    async fn send(&self, msgs: &[Notification]) -> Result<(), MonitorError> {
+        for msg in msgs {
+            info!("Running `{}` for '{}'", self.command, msg.title);
+            let command = self.command.clone();
+            let env = Self::env(msg, self.format());
+            tokio::task::spawn_blocking(move || Self::run(&command, env))
+                .await
+                .map_err(|e| MonitorError::Command(e.to_string()))??;
+        }
+
+        Ok(())
+    }
+
+    fn preview(&self, msgs: &[Notification]) -> String {
+        msgs.iter()
+            .map(|msg| {
+                let env = Self::env(msg, self.format())
+                    .into_iter()
+                    .map(|(name, value)| format!("{}={:?}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("{} {}", env, self.command)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub(crate) struct EmailNotifier {
+    pub(crate) transport: AsyncSmtpTransport<Tokio1Executor>,
+    pub(crate) from: Mailbox,
+    pub(crate) to: Mailbox,
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The HTML about a single notification, with the showtimes linked in `format`.
+pub(crate) fn email_html(msg: &Notification, format: TextFormat) -> String {
+    let mut html = format!(
+        "<p>{message}</p>\n<p><a href=\"{url}\">{title}</a></p>\n<ul>\n",
+        message = msg.message(escape_html, |value| format!(
+            "<b>{}</b>",
+            escape_html(value)
+        )),
+        title = escape_html(&msg.title),
+        url = escape_html(&msg.url)
+    );
+    for showtime in &msg.showtimes {
+        html.push_str(&format!(
+            "<li>{label}: {link}{availability}</li>\n",
+            label = escape_html(&showtime.display_label()),
+            link = showtime.link_text(format),
+            availability = escape_html(&showtime.availability_note())
+        ));
+    }
+    html.push_str("</ul>\n");
+
+    html
+}
+
+impl EmailNotifier {
+    pub(crate) fn subject(msgs: &[Notification]) -> String {
+        LOCALE.subject(
+            &msgs
+                .iter()
+                .map(|msg| msg.title.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+
+    pub(crate) fn html(&self, msgs: &[Notification]) -> String {
+        msgs.iter()
+            .map(|msg| email_html(msg, self.format()))
+            .collect::<Vec<_>>()
+            .join("<hr>\n")
+    }
+
+    pub(crate) async fn send_email(
+        &self,
+        subject: &str,
+        content_type: ContentType,
+        body: String,
+    ) -> Result<(), MonitorError> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(subject)
+            .header(content_type)
+            .body(body)
+            .map_err(|e| MonitorError::Email(e.to_string()))?;
+
+        info!("Sending email '{}' to `{}`", subject, self.to);
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| MonitorError::Email(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &'static str {
+        "Email"
+    }
+
+    fn format(&self) -> TextFormat {
+        TextFormat::Html
+    }
+
+    async fn send(&self, msgs: &[Notification]) -> Result<(), MonitorError> {
+        self.send_email(
+            &Self::subject(msgs),
+            ContentType::TEXT_HTML,
+            self.html(msgs),
+        )
+        .await
+    }
+
+    fn preview(&self, msgs: &[Notification]) -> String {
+        format!("Subject: {}\n\n{}", Self::subject(msgs), self.html(msgs))
+    }
+
+    async fn send_text(&self, text: &str) -> Result<(), MonitorError> {
+        self.send_email("Pathé monitor", ContentType::TEXT_PLAIN, text.to_string())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    use crate::scrape::PLACEHOLDER_THUMBNAIL_URL;
+    use crate::testing::{request, CountingNotifier};
+
+    #[test]
+    fn email_lists_showtimes_as_html() {
+        let notification = Notification {
+            movie: "Fast & Furious".to_string(),
+            title: "Fast & Furious <X>".to_string(),
+            date: "19-08-2021".to_string(),
+            cinema: "Pathé Delft".to_string(),
+            url: "https://pathe.nl/film/1/fast-furious#agenda".to_string(),
+            thumbnail: PLACEHOLDER_THUMBNAIL_URL.to_string(),
+            request_id: "0123abcd".to_string(),
+            message_template: None,
+            showtimes: vec![Showtime {
+                label: "OV".to_string(),
+                start: "19:00".to_string(),
+                end: "21:10".to_string(),
+                link: "https://pathe.nl/tickets/1".to_string(),
+                availability: None,
+                hall: None,
+                preview: false,
+                bookable: true,
+                cinema: None,
+                starts_at: None,
+            }],
+        };
+
+        assert_eq!(
+            EmailNotifier::subject(std::slice::from_ref(&notification)),
+            "Tickets beschikbaar voor Fast & Furious <X>"
+        );
+        let html = email_html(&notification, TextFormat::Html);
+        assert!(html.contains("'<b>Fast &amp; Furious</b>'"));
+        assert!(html.contains(">Fast &amp; Furious &lt;X&gt;</a>"));
+        assert!(
+            html.contains("<li>OV: <a href=\"https://pathe.nl/tickets/1\">19:00 - 21:10</a></li>")
+        );
+    }
+
+    #[test]
+    fn message_template_replaces_the_first_line() {
+        let mut notification = Notification {
+            movie: "Dune".to_string(),
+            title: "Dune: Part Two".to_string(),
+            date: "01-03-2024".to_string(),
+            cinema: "Pathé Delft".to_string(),
+            url: "https://pathe.nl/film/2/dune-part-two#agenda".to_string(),
+            thumbnail: PLACEHOLDER_THUMBNAIL_URL.to_string(),
+            request_id: "0123abcd".to_string(),
+            message_template: None,
+            showtimes: vec![],
+        };
+        assert_eq!(
+            DiscordNotification::messages(std::slice::from_ref(&notification), true, TextFormat::Markdown)[0].content,
+            "Er zijn tickets beschikbaar voor '**Dune**' op **01-03-2024** in **Pathé Delft**, 0 voorstellingen beschikbaar."
+        );
+
+        notification.message_template =
+            Some("Tickets for {movie} on {date} at {cinema} & *more*: {count}".to_string());
+        assert_eq!(
+            DiscordNotification::messages(std::slice::from_ref(&notification), true, TextFormat::Markdown)[0].content,
+            "Tickets for **Dune** on **01-03-2024** at **Pathé Delft** & *more*: 0 voorstellingen beschikbaar"
+        );
+        assert!(telegram_text(&notification, TextFormat::Markdown)
+            .starts_with("Tickets for *Dune* on *01-03-2024* at *Pathé Delft* & \\*more\\*: "));
+        assert!(email_html(&notification, TextFormat::Html).starts_with(
+            "<p>Tickets for <b>Dune</b> on <b>01-03-2024</b> at <b>Pathé Delft</b> &amp; *more*: "
+        ));
+    }
+
+    #[test]
+    fn webhook_template_is_rendered_as_json() {
+        let notification = Notification {
+            movie: "The \"Green\" Knight".to_string(),
+            title: "The Green Knight".to_string(),
+            date: "19-08-2021".to_string(),
+            cinema: "Pathé Delft".to_string(),
+            url: "https://pathe.nl/film/12345/the-green-knight#agenda".to_string(),
+            thumbnail: PLACEHOLDER_THUMBNAIL_URL.to_string(),
+            request_id: "0123abcd".to_string(),
+            message_template: None,
+            showtimes: vec![
+                Showtime {
+                    label: "OV".to_string(),
+                    start: "19:00".to_string(),
+                    end: "21:10".to_string(),
+                    link: "https://pathe.nl/tickets/1".to_string(),
+                    availability: None,
+                    hall: None,
+                    preview: false,
+                    bookable: true,
+                    cinema: None,
+                    starts_at: None,
+                },
+                Showtime {
+                    label: "NL".to_string(),
+                    start: "14:30".to_string(),
+                    end: "16:40".to_string(),
+                    link: "https://pathe.nl/tickets/2".to_string(),
+                    availability: Some("uitverkocht".to_string()),
+                    hall: None,
+                    preview: false,
+                    bookable: true,
+                    cinema: None,
+                    starts_at: None,
+                },
+            ],
+        };
+
+        let payload = render_webhook_template(
+            r#"{"text": "{movie} op {date} in {cinema}: {showtimes}"}"#,
+            &notification,
+            TextFormat::Plain,
+        )
+        .unwrap();
+        assert_eq!(
+            payload,
+            json!({ "text": "The \"Green\" Knight op 19-08-2021 in Pathé Delft: OV 19:00 - 21:10: https://pathe.nl/tickets/1, NL 14:30 - 16:40: https://pathe.nl/tickets/2 ⚠️ uitverkocht" })
+        );
+        assert!(render_webhook_template(
+            DEFAULT_WEBHOOK_TEMPLATE,
+            &notification,
+            TextFormat::Plain
+        )
+        .is_ok());
+        assert!(
+            render_webhook_template(r#"{"text": {movie}}"#, &notification, TextFormat::Plain)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_discord_messages_are_resent() {
+        let mut server = mockito::Server::new_async().await;
+        let limited = server
+            .mock("POST", "/webhook")
+            .with_status(429)
+            .with_header("retry-after", "0.01")
+            .expect(1)
+            .create_async()
+            .await;
+        let notifier = DiscordNotifier {
+            client: Client::new(),
+            webhook_url: format!("{}/webhook", server.url()),
+        };
+        let sent = server
+            .mock("POST", "/webhook")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+
+        notifier.send_text("hallo").await.unwrap();
+        limited.assert_async().await;
+        sent.assert_async().await;
+
+        server
+            .mock("POST", "/webhook")
+            .with_status(400)
+            .with_body(r#"{"message": "Cannot send an empty message"}"#)
+            .create_async()
+            .await;
+        let error = notifier.send_text("").await.unwrap_err().to_string();
+        assert!(error.contains("400"));
+        assert!(error.contains("Cannot send an empty message"));
+    }
+
+    #[test]
+    fn request_webhook_replaces_global_discord_webhook() {
+        let client = Client::new();
+        let notifiers: Vec<Arc<dyn Notifier>> = vec![
+            Arc::new(DiscordNotifier {
+                client: client.clone(),
+                webhook_url: "https://discord.com/api/webhooks/global".to_string(),
+            }),
+            Arc::new(CountingNotifier(Arc::new(AtomicUsize::new(0)))),
+        ];
+        let mut request = request();
+
+        assert_eq!(request_notifiers(&request, &client, &notifiers).len(), 2);
+
+        request.webhook_url = Some("https://discord.com/api/webhooks/family".to_string());
+        let names: Vec<_> = request_notifiers(&request, &client, &notifiers)
+            .iter()
+            .map(|notifier| notifier.name())
+            .collect();
+        assert_eq!(names, vec!["Counting", "Discord"]);
+        assert!(!request_notifiers(&request, &client, &[]).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn on_match_command_gets_the_movie_in_its_environment() {
+        let mut request = request();
+        request.on_match_command = Some(
+            r#"test "$PATHE_TITLE" = "Dune: Part Two" && test "$PATHE_CINEMA" = "Pathé Delft""#
+                .to_string(),
+        );
+        let notifiers = request_notifiers(&request, &Client::new(), &[]);
+        assert_eq!(notifiers.len(), 1);
+
+        let mut notification = Notification {
+            movie: "Dune".to_string(),
+            title: "Dune: Part Two".to_string(),
+            date: "01-03-2024".to_string(),
+            cinema: "Pathé Delft".to_string(),
+            url: "https://pathe.nl/film/2/dune-part-two#agenda".to_string(),
+            thumbnail: PLACEHOLDER_THUMBNAIL_URL.to_string(),
+            request_id: request.id(),
+            message_template: None,
+            showtimes: vec![],
+        };
+        notifiers[0]
+            .send(std::slice::from_ref(&notification))
+            .await
+            .unwrap();
+
+        notification.cinema = "Pathé Spuimarkt".to_string();
+        match notifiers[0].send(&[notification]).await {
+            Err(MonitorError::Command(e)) => assert!(e.contains("exited with exit status: 1")),
+            result => panic!("expected the command to fail, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn errors_are_reported_once_an_hour_per_request() {
+        let mut reporter = ErrorReporter {
+            notifier: DiscordNotifier {
+                client: Client::new(),
+                webhook_url: "https://discord.com/api/webhooks/errors".to_string(),
+            },
+            reported: HashMap::new(),
+        };
+        let now = Instant::now();
+
+        assert!(reporter.should_report("0123abcd", now));
+        assert!(reporter.should_report("4567ef01", now));
+        assert!(!reporter.should_report("0123abcd", now + std::time::Duration::from_secs(59 * 60)));
+        assert!(reporter.should_report("0123abcd", now + ErrorReporter::INTERVAL));
+    }
+}
@@ -0,0 +1,897 @@
+use log::warn;
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::cinema::CinemaSpec;
+use crate::error::MonitorError;
+use crate::locale::{Locale, LOCALE};
+use crate::notifications::{Notification, Showtime};
+
+pub(crate) const DEFAULT_FUZZY_THRESHOLD: usize = 3;
+pub(crate) const DATE_FORMAT: &str = "%d-%m-%Y"; // the format Pathé expects in its `date` query param
+pub(crate) const TIME_FORMAT: &str = "%H:%M";
+
+/// The missing halls are only warned about once, they are missing from every schedule or none.
+static MISSING_HALL_WARNED: AtomicBool = AtomicBool::new(false);
+/// The `movie` that matches every title, to be notified about each movie that is new on the schedule.
+pub(crate) const ANY_MOVIE: &str = "*";
+/// Absolute dates may also be written in these formats, they are sent to Pathé in `DATE_FORMAT`.
+pub(crate) const ALTERNATIVE_DATE_FORMATS: [&str; 2] = ["%Y-%m-%d", "%d/%m/%Y"];
+
+/// How the configured movie is compared to the titles on the schedule.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MatchMode {
+    /// case-insensitive equality
+    Exact,
+    /// the scheduled title contains the movie, ignoring case and punctuation
+    #[default]
+    Contains,
+    /// the Levenshtein distance between both, ignoring case and punctuation, is within the threshold
+    Fuzzy,
+}
+
+/// Lowercases and strips punctuation, so "Dune: Part Two" and "dune part two" compare equal.
+pub(crate) fn normalize_title(title: &str) -> String {
+    title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decomposes the text and drops the accents, so "Pokémon" becomes "Pokemon".
+pub(crate) fn strip_accents(text: &str) -> String {
+    text.nfkd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+/// Resolves a single date, either absolute in `DATE_FORMAT` or one of `ALTERNATIVE_DATE_FORMATS`, or relative:
+/// `today`, `tomorrow` or `+<days>d`.
+pub(crate) fn resolve_date(date: &str, today: NaiveDate) -> Result<NaiveDate, MonitorError> {
+    let invalid = || MonitorError::InvalidDate(date.to_string());
+
+    match date.trim().to_lowercase().as_str() {
+        "today" => Ok(today),
+        "tomorrow" => today.succ_opt().ok_or_else(invalid),
+        relative if relative.starts_with('+') && relative.ends_with('d') => {
+            // an i32 of days always fits a `Duration`, `Duration::try_days` isn't in this chrono yet
+            let days: i32 = relative[1..relative.len() - 1]
+                .parse()
+                .map_err(|_| invalid())?;
+            today
+                .checked_add_signed(Duration::days(days.into()))
+                .ok_or_else(invalid)
+        }
+        absolute => std::iter::once(DATE_FORMAT)
+            .chain(ALTERNATIVE_DATE_FORMATS)
+            .find_map(|format| NaiveDate::parse_from_str(absolute, format).ok())
+            .ok_or_else(invalid),
+    }
+}
+
+/// One date, a list of dates or an inclusive `{ from, to }` range of dates.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum DateSpec {
+    Single(String),
+    List(Vec<String>),
+    Range { from: String, to: String },
+}
+
+impl DateSpec {
+    pub(crate) fn dates(&self, today: NaiveDate) -> Result<Vec<NaiveDate>, MonitorError> {
+        Ok(match self {
+            DateSpec::Single(date) => vec![resolve_date(date, today)?],
+            DateSpec::List(dates) => dates
+                .iter()
+                .map(|date| resolve_date(date, today))
+                .collect::<Result<_, _>>()?,
+            DateSpec::Range { from, to } => {
+                let from = resolve_date(from, today)?;
+                let to = resolve_date(to, today)?;
+                from.iter_days().take_while(|date| *date <= to).collect()
+            }
+        })
+    }
+
+    /// Expands into every date still to check, formatted the way Pathé expects.
+    pub(crate) fn resolve(&self, today: NaiveDate) -> Result<Vec<String>, MonitorError> {
+        Ok(self
+            .dates(today)?
+            .iter()
+            .filter(|date| **date >= today)
+            .map(|date| date.format(DATE_FORMAT).to_string())
+            .collect())
+    }
+
+    /// Whether every date has passed, so there is nothing left to check.
+    pub(crate) fn is_past(&self, today: NaiveDate) -> bool {
+        match self.dates(today) {
+            Ok(dates) => dates.iter().all(|date| *date < today),
+            Err(_) => false,
+        }
+    }
+}
+
+impl DateSpec {
+    pub(crate) fn describe(&self, locale: Locale) -> String {
+        match self {
+            DateSpec::Single(date) => date.clone(),
+            DateSpec::List(dates) => dates.join(", "),
+            DateSpec::Range { from, to } => locale.date_range(from, to),
+        }
+    }
+}
+
+impl Display for DateSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(*LOCALE))
+    }
+}
+
+/// A daily window like `23:00-07:00` during which notifications are held, it may wrap past midnight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub(crate) fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = MonitorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MonitorError::InvalidQuietHours(s.to_string());
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse =
+            |time: &str| NaiveTime::parse_from_str(time.trim(), TIME_FORMAT).map_err(|_| invalid());
+
+        Ok(QuietHours {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{}-{}",
+            self.start.format(TIME_FORMAT),
+            self.end.format(TIME_FORMAT)
+        ))
+    }
+}
+
+/// One movie title or a list of titles, any of which may match.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum MovieSpec {
+    Single(String),
+    List(Vec<String>),
+}
+
+impl MovieSpec {
+    pub(crate) fn titles(&self) -> &[String] {
+        match self {
+            MovieSpec::Single(movie) => std::slice::from_ref(movie),
+            MovieSpec::List(movies) => movies,
+        }
+    }
+}
+
+impl MovieSpec {
+    pub(crate) fn describe(&self, locale: Locale) -> String {
+        let titles: Vec<String> = self
+            .titles()
+            .iter()
+            .map(|title| match title.as_str() {
+                ANY_MOVIE => locale.any_movie().to_string(),
+                title => format!("'{}'", title),
+            })
+            .collect();
+        titles.join(locale.or())
+    }
+}
+
+impl Display for MovieSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(*LOCALE))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MovieMonitorRequest {
+    pub cinema: CinemaSpec,
+    pub date: DateSpec,
+    pub movie: MovieSpec,
+    /// the end of the movie's page on pathe.nl, e.g. `12345/the-green-knight`, matched before the title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub movie_slug: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub match_mode: MatchMode,
+    /// compare titles without accents, set to `false` when the accents tell movies apart
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub normalize: bool,
+    /// maximum edit distance for `MatchMode::Fuzzy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuzzy_threshold: Option<usize>,
+    /// only showtimes whose label carries one of these versions, e.g. `OV` or `NL`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub versions: Option<Vec<String>>,
+    /// only showtimes whose label carries one of these experiences, e.g. `IMAX` or `Dolby Cinema`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experiences: Option<Vec<String>>,
+    /// only showtimes in one of these halls, e.g. `Zaal 6` or `4DX`, when the schedule names the hall
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub halls: Option<Vec<String>>,
+    /// set to `false` to skip previews and member-only screenings
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub include_previews: bool,
+    /// only showtimes that can be booked, not the ones listed before the pre-sale opens or sold out
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub require_bookable: bool,
+    /// Discord webhook to notify instead of `DISCORD_WEBHOOK_URL`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    /// shell command to run for every match, with the movie in `PATHE_*` environment variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_match_command: Option<String>,
+    /// only showtimes starting at or after this time (HH:MM)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earliest: Option<String>,
+    /// only showtimes starting at or before this time (HH:MM)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest: Option<String>,
+    /// also notify once a notified movie disappears from the schedule
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub notify_on_removal: bool,
+    /// first line of the notifications about this request, instead of `MESSAGE_TEMPLATE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_template: Option<String>,
+    /// `HH:MM-HH:MM` to hold notifications in, instead of `QUIET_HOURS`, or `off` to never hold them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<String>,
+    /// timezone to resolve the dates of this request in, instead of `TIMEZONE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// requests with a higher priority are checked first, those with the same priority in the order of the config
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub priority: i32,
+    /// set to `false` to stop checking the request without removing it from the config
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    /// disable the request once it has been notified, instead of checking it for changes
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub oneshot: bool,
+    /// minutes after `last_notified` in which no other showtimes are notified, however the schedule changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_minutes: Option<u32>,
+    /// when the showtimes of this request were last notified, written back by the monitor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_notified: Option<DateTime<FixedOffset>>,
+    /// the notified showtimes as `<date> | <title> | <label> <start> - <end>`, written back by the monitor
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub seen_showtimes: Vec<String>,
+    /// other titles per movie of the request, filled in from the `aliases` of the config file
+    #[serde(skip)]
+    pub aliases: HashMap<String, Vec<String>>,
+}
+
+/// Whether `text` contains the words of `phrase` in order, ignoring case, e.g. `OV Dolby Cinema` contains `dolby cinema`.
+pub(crate) fn contains_words(text: &str, phrase: &str) -> bool {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+    let phrase: Vec<String> = phrase.split_whitespace().map(str::to_lowercase).collect();
+
+    !phrase.is_empty()
+        && words
+            .windows(phrase.len())
+            .any(|window| window == &phrase[..])
+}
+
+pub(crate) fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl MovieMonitorRequest {
+    /// The timezone of the request, `default` unless it has its own `timezone`.
+    pub(crate) fn tz(&self, default: chrono_tz::Tz) -> Result<chrono_tz::Tz, MonitorError> {
+        match &self.timezone {
+            Some(timezone) => timezone.parse().map_err(|_| MonitorError::InvalidTimezone {
+                timezone: timezone.clone(),
+                request: self.to_string(),
+            }),
+            None => Ok(default),
+        }
+    }
+
+    /// The quiet hours of the request, `default` unless it has its own `quiet_hours`.
+    pub(crate) fn quiet_hours(
+        &self,
+        default: Option<QuietHours>,
+    ) -> Result<Option<QuietHours>, MonitorError> {
+        match self.quiet_hours.as_deref().map(str::trim) {
+            None => Ok(default),
+            Some(off) if off.eq_ignore_ascii_case("off") => Ok(None),
+            Some(quiet_hours) => quiet_hours.parse().map(Some),
+        }
+    }
+
+    /// Checks what deserializing can't, like whether every date and the timezone can be resolved.
+    pub(crate) fn validate(&self, today: NaiveDate) -> Result<(), MonitorError> {
+        if self
+            .movie
+            .titles()
+            .iter()
+            .all(|title| title.trim().is_empty())
+        {
+            return Err(MonitorError::EmptyMovie(self.to_string()));
+        }
+        self.date.resolve(today).map_err(|e| match e {
+            MonitorError::InvalidDate(date) => MonitorError::InvalidRequestDate {
+                date,
+                request: self.to_string(),
+            },
+            e => e,
+        })?;
+        self.tz(chrono_tz::UTC)?;
+        self.quiet_hours(None)?;
+        self.window()?;
+        if let Some(url) = &self.webhook_url {
+            if !matches!(reqwest::Url::parse(url), Ok(parsed) if parsed.scheme().starts_with("http"))
+            {
+                return Err(MonitorError::InvalidWebhook {
+                    url: url.clone(),
+                    request: self.to_string(),
+                });
+            }
+        }
+        if matches!(&self.on_match_command, Some(command) if command.trim().is_empty()) {
+            return Err(MonitorError::EmptyCommand(self.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the request matches every title, and only notifies about titles it hasn't notified on a date yet.
+    pub(crate) fn is_catch_all(&self) -> bool {
+        self.movie
+            .titles()
+            .iter()
+            .any(|movie| movie.trim() == ANY_MOVIE)
+    }
+
+    pub fn matches(&self, title: &str) -> bool {
+        self.matching_movie(title).is_some()
+    }
+
+    /// Whether the link to a scheduled movie's page ends in the `movie_slug`, e.g. `/film/12345/the-green-knight`
+    /// ends in `the-green-knight`.
+    pub(crate) fn matches_slug(&self, href: &str) -> bool {
+        let slug = match &self.movie_slug {
+            Some(slug) => slug.trim().trim_matches('/'),
+            None => return false,
+        };
+        let path = href
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+
+        !slug.is_empty() && path.ends_with(slug) && path[..path.len() - slug.len()].ends_with('/')
+    }
+
+    /// The configured movie that matches `title`, if any.
+    pub(crate) fn matching_movie(&self, title: &str) -> Option<&str> {
+        let normalize = |text: &str| {
+            if self.normalize {
+                strip_accents(text)
+            } else {
+                text.to_string()
+            }
+        };
+        let title = &normalize(title);
+
+        self.movie
+            .titles()
+            .iter()
+            .map(String::as_str)
+            .find(|movie| {
+                if movie.trim() == ANY_MOVIE {
+                    return true;
+                }
+                let aliases = self
+                    .aliases
+                    .get(*movie)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                std::iter::once(*movie)
+                    .chain(aliases.iter().map(String::as_str))
+                    .any(|movie| {
+                        let movie = &normalize(movie);
+                        match self.match_mode {
+                            MatchMode::Exact => title.to_lowercase() == movie.to_lowercase(),
+                            MatchMode::Contains => {
+                                normalize_title(title).contains(&normalize_title(movie))
+                            }
+                            MatchMode::Fuzzy => {
+                                strsim::levenshtein(
+                                    &normalize_title(title),
+                                    &normalize_title(movie),
+                                ) <= self.fuzzy_threshold.unwrap_or(DEFAULT_FUZZY_THRESHOLD)
+                            }
+                        }
+                    })
+            })
+    }
+
+    /// Whether the showtime passes the filters of this request.
+    pub(crate) fn allows(&self, showtime: &Showtime) -> bool {
+        self.allows_version(showtime)
+            && self.allows_experience(showtime)
+            && self.allows_hall(showtime)
+            && self.within_window(showtime)
+            && (self.include_previews || !showtime.preview)
+            && (!self.require_bookable || showtime.bookable)
+    }
+
+    pub(crate) fn allows_version(&self, showtime: &Showtime) -> bool {
+        match &self.versions {
+            Some(versions) => showtime.label.split_whitespace().any(|word| {
+                versions
+                    .iter()
+                    .any(|version| version.eq_ignore_ascii_case(word))
+            }),
+            None => true,
+        }
+    }
+
+    pub(crate) fn allows_experience(&self, showtime: &Showtime) -> bool {
+        match &self.experiences {
+            Some(experiences) => experiences
+                .iter()
+                .any(|experience| contains_words(&showtime.label, experience)),
+            None => true,
+        }
+    }
+
+    /// Keeps every showtime when the schedule doesn't name the halls, instead of notifying none of them.
+    pub(crate) fn allows_hall(&self, showtime: &Showtime) -> bool {
+        match (&self.halls, &showtime.hall) {
+            (Some(halls), Some(hall)) => halls.iter().any(|wanted| contains_words(hall, wanted)),
+            (Some(_), None) => {
+                if !MISSING_HALL_WARNED.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "the schedule doesn't name the hall of showtimes, ignoring the `halls` of {} and the other requests",
+                        self
+                    );
+                }
+                true
+            }
+            (None, _) => true,
+        }
+    }
+
+    pub(crate) fn within_window(&self, showtime: &Showtime) -> bool {
+        if self.earliest.is_none() && self.latest.is_none() {
+            return true;
+        }
+
+        let start = match showtime.starts_at {
+            Some(starts_at) => starts_at.time(),
+            None => {
+                warn!(
+                    "could not parse start time '{}' of {}, keeping showtime",
+                    showtime.start, self
+                );
+                return true;
+            }
+        };
+
+        // validated when loading the config
+        match self.window() {
+            // an evening window like 18:00-01:00 continues past midnight
+            Ok((Some(earliest), Some(latest))) if latest < earliest => {
+                start >= earliest || start <= latest
+            }
+            Ok((earliest, latest)) => {
+                !matches!(earliest, Some(earliest) if start < earliest)
+                    && !matches!(latest, Some(latest) if start > latest)
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// The `earliest` and `latest` start of the showtimes to notify about.
+    fn window(&self) -> Result<(Option<NaiveTime>, Option<NaiveTime>), MonitorError> {
+        let bound = |bound: &Option<String>| {
+            bound
+                .as_deref()
+                .map(|time| {
+                    NaiveTime::parse_from_str(time.trim(), TIME_FORMAT).map_err(|_| {
+                        MonitorError::InvalidTime {
+                            time: time.to_string(),
+                            request: self.to_string(),
+                        }
+                    })
+                })
+                .transpose()
+        };
+
+        Ok((bound(&self.earliest)?, bound(&self.latest)?))
+    }
+
+    /// Short identifier of what is requested, to trace a notification back to its request.
+    ///
+    /// A 32-bit FNV-1a hash, so the ID stays the same across builds and releases of the monitor.
+    pub fn id(&self) -> String {
+        let cinemas: Vec<String> = self
+            .cinema
+            .cinemas()
+            .iter()
+            .map(|cinema| cinema.id().to_string())
+            .collect();
+        // always in Dutch, so the ID doesn't change with `LOCALE`
+        let canonical = format!(
+            "{}|{}|{}",
+            cinemas.join(","),
+            self.date.describe(Locale::Nl),
+            self.movie.describe(Locale::Nl)
+        );
+        let hash = canonical.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+        format!("{:08x}", hash)
+    }
+
+    pub(crate) fn seen_prefix(date: &str, title: &str) -> String {
+        format!("{} | {} | ", date, title)
+    }
+
+    /// The showtimes of the notification as they are kept in `seen_showtimes`, sorted.
+    pub(crate) fn seen_entries(notification: &Notification) -> Vec<String> {
+        let prefix = Self::seen_prefix(&notification.date, &notification.title);
+        let mut entries: Vec<String> = notification
+            .showtimes
+            .iter()
+            .map(|showtime| match &showtime.cinema {
+                Some(cinema) => format!(
+                    "{}{} {} {} - {}",
+                    prefix, cinema, showtime.label, showtime.start, showtime.end
+                ),
+                None => format!(
+                    "{}{} {} - {}",
+                    prefix, showtime.label, showtime.start, showtime.end
+                ),
+            })
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// The notified showtimes of `title` on `date`, sorted so they can be compared with a fresh scrape.
+    pub(crate) fn seen_on(&self, date: &str, title: &str) -> Vec<String> {
+        let prefix = Self::seen_prefix(date, title);
+        let mut seen: Vec<String> = self
+            .seen_showtimes
+            .iter()
+            .filter(|entry| entry.starts_with(&prefix))
+            .cloned()
+            .collect();
+        seen.sort();
+        seen
+    }
+
+    /// Replaces the notified showtimes of the notification and marks the request as notified at `now`.
+    pub(crate) fn mark_notified(
+        &mut self,
+        notification: &Notification,
+        now: DateTime<FixedOffset>,
+    ) {
+        self.mark_seen(notification);
+        self.last_notified = Some(now);
+    }
+
+    /// Remembers the showtimes of the notification without it having been sent, so they aren't notified later.
+    pub(crate) fn mark_seen(&mut self, notification: &Notification) {
+        self.forget(&notification.date, &notification.title);
+        self.seen_showtimes.extend(Self::seen_entries(notification));
+    }
+
+    /// When the `cooldown_minutes` after the last notification end, if they haven't at `now`.
+    pub(crate) fn cooldown_until(
+        &self,
+        now: DateTime<FixedOffset>,
+    ) -> Option<DateTime<FixedOffset>> {
+        let until = self
+            .last_notified?
+            .checked_add_signed(chrono::Duration::minutes(self.cooldown_minutes?.into()))?;
+        (until > now).then_some(until)
+    }
+
+    /// The titles with notified showtimes on `date`.
+    pub(crate) fn seen_titles(&self, date: &str) -> Vec<String> {
+        let prefix = format!("{} | ", date);
+        let mut titles: Vec<String> = self
+            .seen_showtimes
+            .iter()
+            .filter_map(|entry| entry.strip_prefix(&prefix))
+            .filter_map(|entry| entry.split(" | ").next())
+            .map(str::to_string)
+            .collect();
+        titles.sort();
+        titles.dedup();
+        titles
+    }
+
+    /// Forgets the notified showtimes of `title` on `date`.
+    pub(crate) fn forget(&mut self, date: &str, title: &str) {
+        let prefix = Self::seen_prefix(date, title);
+        self.seen_showtimes
+            .retain(|entry| !entry.starts_with(&prefix));
+    }
+}
+
+impl Display for MovieMonitorRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&LOCALE.request(
+            &self.movie.to_string(),
+            &self.date.to_string(),
+            &self.cinema.to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scrape::parse_start;
+    use crate::testing::request;
+
+    #[test]
+    fn showtimes_are_filtered_by_experience() {
+        let mut request = request();
+        let showtime = |label: &str| Showtime {
+            label: label.to_string(),
+            start: "19:00".to_string(),
+            end: "21:10".to_string(),
+            link: "https://pathe.nl/tickets/1".to_string(),
+            availability: None,
+            hall: None,
+            preview: false,
+            bookable: true,
+            cinema: None,
+            starts_at: None,
+        };
+
+        request.experiences = Some(vec!["imax".to_string(), "Dolby Cinema".to_string()]);
+        assert!(request.allows(&showtime("OV IMAX")));
+        assert!(request.allows(&showtime("Dolby Cinema NL")));
+        assert!(!request.allows(&showtime("OV Dolby Atmos")));
+        assert!(!request.allows(&showtime("OV")));
+
+        request.versions = Some(vec!["NL".to_string()]);
+        assert!(!request.allows(&showtime("OV IMAX")));
+        assert!(request.allows(&showtime("NL IMAX")));
+    }
+
+    #[test]
+    fn showtimes_are_filtered_by_time_window() {
+        let mut request = request();
+        let showtime = |start: &str| Showtime {
+            label: "OV".to_string(),
+            start: start.to_string(),
+            end: "23:00".to_string(),
+            link: "https://pathe.nl/tickets/1".to_string(),
+            availability: None,
+            hall: None,
+            preview: false,
+            bookable: true,
+            cinema: None,
+            starts_at: parse_start("19-08-2021", start),
+        };
+
+        request.earliest = Some("18:00".to_string());
+        request.latest = Some("21:30".to_string());
+        assert!(!request.allows(&showtime("10:15")));
+        assert!(request.allows(&showtime("18:00")));
+        assert!(request.allows(&showtime("21:30")));
+        assert!(!request.allows(&showtime("22:45")));
+        assert!(request.allows(&showtime("later")));
+
+        request.latest = Some("01:00".to_string());
+        assert!(request.allows(&showtime("22:45")));
+        assert!(request.allows(&showtime("00:30")));
+        assert!(!request.allows(&showtime("01:15")));
+        assert!(!request.allows(&showtime("10:15")));
+
+        let today = NaiveDate::from_ymd(2021, 8, 19);
+        assert!(request.validate(today).is_ok());
+        request.earliest = Some("7pm".to_string());
+        assert_eq!(
+            request.validate(today).unwrap_err().to_string(),
+            format!("invalid time `7pm` for {}, use HH:MM", request)
+        );
+    }
+
+    #[test]
+    fn requests_are_enabled_unless_disabled() {
+        let request: MovieMonitorRequest = serde_json::from_str(
+            r#"{"cinema": "Spuimarkt", "date": "19-08-2021", "movie": "Dune"}"#,
+        )
+        .unwrap();
+        assert!(request.enabled);
+        assert!(serde_json::to_value(&request)
+            .unwrap()
+            .get("enabled")
+            .is_none());
+
+        let request: MovieMonitorRequest = serde_json::from_str(
+            r#"{"cinema": "Spuimarkt", "date": "19-08-2021", "movie": "Dune", "enabled": false}"#,
+        )
+        .unwrap();
+        assert!(!request.enabled);
+        assert_eq!(serde_json::to_value(&request).unwrap()["enabled"], false);
+    }
+
+    #[test]
+    fn request_timezone_overrides_the_global_one() {
+        let mut request = request();
+        assert_eq!(
+            request.tz(chrono_tz::Europe::Amsterdam).unwrap(),
+            chrono_tz::Europe::Amsterdam
+        );
+
+        request.timezone = Some("Europe/London".to_string());
+        assert_eq!(
+            request.tz(chrono_tz::Europe::Amsterdam).unwrap(),
+            chrono_tz::Europe::London
+        );
+
+        request.timezone = Some("Europe/Atlantis".to_string());
+        let error = request.tz(chrono_tz::UTC).unwrap_err().to_string();
+        assert!(error.contains("Europe/Atlantis"));
+        assert!(error.contains("The Green Knight"));
+    }
+
+    #[test]
+    fn accents_are_ignored_unless_disabled() {
+        let mut request = request();
+        request.movie = MovieSpec::Single("Pokemon".to_string());
+        request.match_mode = MatchMode::Exact;
+        assert!(request.matches("Pokémon"));
+        assert_eq!(request.matching_movie("POKÉMON"), Some("Pokemon"));
+
+        request.movie = MovieSpec::Single("Amélie".to_string());
+        request.match_mode = MatchMode::Contains;
+        assert!(request.matches("Le Fabuleux Destin d'Amelie Poulain"));
+
+        request.normalize = false;
+        assert!(!request.matches("Le Fabuleux Destin d'Amelie Poulain"));
+        assert!(request.matches("Le Fabuleux Destin d'Amélie Poulain"));
+    }
+
+    #[test]
+    fn match_modes() {
+        let mut request = request();
+        request.movie = MovieSpec::Single("Dune: Part Two".to_string());
+
+        request.match_mode = MatchMode::Exact;
+        assert!(request.matches("dune: part two"));
+        assert!(!request.matches("Dune Part Two"));
+
+        request.match_mode = MatchMode::Contains;
+        assert!(request.matches("Dune Part Two (IMAX)"));
+        assert!(!request.matches("Dune"));
+
+        request.match_mode = MatchMode::Fuzzy;
+        assert!(request.matches("Dune Part 2"));
+        assert!(!request.matches("Dune - Deel Twee"));
+
+        request.match_mode = MatchMode::Contains;
+        request.movie = MovieSpec::List(vec!["Thor".to_string(), "Dune".to_string()]);
+        assert_eq!(request.matching_movie("Dune Part Two"), Some("Dune"));
+
+        request.movie_slug = Some("12345/the-green-knight".to_string());
+        assert!(request.matches_slug("/film/12345/the-green-knight"));
+        assert!(request.matches_slug("https://pathe.nl/film/12345/the-green-knight/#agenda"));
+        assert!(!request.matches_slug("/film/912345/the-green-knight"));
+        request.movie_slug = Some("the-green-knight".to_string());
+        assert!(request.matches_slug("/film/12345/the-green-knight"));
+        assert!(!request.matches_slug("/film/12345/not-the-green-knight"));
+        assert!(!request.matches("Oppenheimer"));
+    }
+
+    #[test]
+    fn date_specs_resolve_against_today() {
+        let today = NaiveDate::from_ymd_opt(2021, 8, 19).unwrap();
+
+        let single = DateSpec::Single("tomorrow".to_string());
+        assert_eq!(single.resolve(today).unwrap(), vec!["20-08-2021"]);
+
+        let list = DateSpec::List(vec!["today".to_string(), "+3d".to_string()]);
+        assert_eq!(
+            list.resolve(today).unwrap(),
+            vec!["19-08-2021", "22-08-2021"]
+        );
+
+        let range = DateSpec::Range {
+            from: "30-08-2021".to_string(),
+            to: "01-09-2021".to_string(),
+        };
+        assert_eq!(
+            range.resolve(today).unwrap(),
+            vec!["30-08-2021", "31-08-2021", "01-09-2021"]
+        );
+
+        let ongoing = DateSpec::Range {
+            from: "18-08-2021".to_string(),
+            to: "tomorrow".to_string(),
+        };
+        assert_eq!(
+            ongoing.resolve(today).unwrap(),
+            vec!["19-08-2021", "20-08-2021"]
+        );
+        assert!(!ongoing.is_past(today));
+        assert!(DateSpec::Single("18-08-2021".to_string()).is_past(today));
+        for far in ["+999999999999d", "+2147483647d"] {
+            assert!(matches!(
+                resolve_date(far, today),
+                Err(MonitorError::InvalidDate(_))
+            ));
+        }
+        assert!(!DateSpec::Single("today".to_string()).is_past(today));
+
+        assert!(DateSpec::Single("2021-13-01".to_string())
+            .resolve(today)
+            .is_err());
+        assert!(DateSpec::Single("31-09-2021".to_string())
+            .resolve(today)
+            .is_err());
+        assert!(DateSpec::Single("19-08".to_string())
+            .resolve(today)
+            .is_err());
+
+        let alternatives = DateSpec::List(vec!["2021-08-20".to_string(), "21/08/2021".to_string()]);
+        assert_eq!(
+            alternatives.resolve(today).unwrap(),
+            vec!["20-08-2021", "21-08-2021"]
+        );
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        let mut request = request();
+        assert_eq!(request.id(), "c465c259");
+        request.date = DateSpec::Single("20-08-2021".to_string());
+        assert_ne!(request.id(), "c465c259");
+    }
+}
@@ -0,0 +1,2012 @@
+//! Scrapes the schedule pages of pathe.nl.
+
+use log::{debug, info, trace, warn};
+
+use reqwest::Client;
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::api::{scan_json_schedule, FetchedSchedule, ScheduleSource};
+use crate::cinema::CinemaRef;
+use crate::error::MonitorError;
+use crate::locale::LOCALE;
+use crate::notifications::{notify, notify_text, Notification, Notifier, Showtime};
+use crate::request::{MovieMonitorRequest, ANY_MOVIE, DATE_FORMAT, TIME_FORMAT};
+use crate::status::SCRAPE_ERRORS_TOTAL;
+
+pub(crate) const RETRY_BASE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+pub(crate) const DEFAULT_DEBUG_DUMP_DIR: &str = "debug";
+pub(crate) const PLACEHOLDER_THUMBNAIL_URL: &str = "https://placehold.co/300x450?text=Path%C3%A9";
+/// Attributes of a showtime that may link straight to the seat selection, preferred over its `data-href`.
+pub(crate) const ORDER_LINK_ATTRIBUTES: [&str; 2] = ["data-order-href", "data-order-url"];
+/// Classes and label words that mark a preview or member-only screening, e.g. `schedule-time--unlimited` or `Voorpremière`.
+pub(crate) const PREVIEW_MARKERS: [&str; 4] = ["preview", "voorpremi", "unlimited", "members"];
+/// Classes that mark a showing which can't be booked (yet), e.g. `schedule-time--disabled`.
+pub(crate) const NOT_BOOKABLE_MARKERS: [&str; 4] =
+    ["disabled", "unavailable", "coming-soon", "presale"];
+/// Showtimes are listed in the local time of the cinemas.
+pub(crate) const PATHE_TZ: Tz = chrono_tz::Europe::Amsterdam;
+/// Night showings starting before this hour are listed on the schedule of the previous evening.
+pub(crate) const NIGHT_SHOWING_HOUR: u32 = 6;
+/// How many pages of a single schedule are followed at most, in case the next page links back.
+pub(crate) const MAX_SCHEDULE_PAGES: usize = 10;
+
+pub(crate) fn parse_selector(selector: &str) -> Result<Selector, MonitorError> {
+    Selector::parse(selector).map_err(|_| MonitorError::ParseSelector(selector.to_string()))
+}
+
+pub(crate) fn select_first<'a>(
+    element: ElementRef<'a>,
+    selector: &str,
+) -> Result<ElementRef<'a>, MonitorError> {
+    element
+        .select(&parse_selector(selector)?)
+        .next()
+        .ok_or_else(|| MonitorError::MissingElement(selector.to_string()))
+}
+
+pub(crate) fn first_text<'a>(
+    element: ElementRef<'a>,
+    selector: &str,
+) -> Result<&'a str, MonitorError> {
+    select_first(element, selector)?
+        .text()
+        .next()
+        .ok_or_else(|| MonitorError::MissingElement(format!("{} text", selector)))
+}
+
+pub(crate) fn required_attr<'a>(
+    element: ElementRef<'a>,
+    attr: &str,
+) -> Result<&'a str, MonitorError> {
+    element.value().attr(attr).ok_or_else(|| {
+        MonitorError::MissingElement(format!("{}[{}]", element.value().name(), attr))
+    })
+}
+
+/// The CSS selectors used to scrape pathe.nl, each can be overridden in the `selectors` section of the config.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Selectors {
+    /// a movie on the schedule
+    pub schedule_item: String,
+    /// the title link of a movie, within a `schedule_item`
+    pub title: String,
+    /// the poster image of a movie, within a `schedule_item`
+    pub poster: String,
+    /// a showing of a movie, within a `schedule_item`
+    pub showtime: String,
+    pub showtime_start: String,
+    pub showtime_end: String,
+    pub showtime_label: String,
+    pub showtime_availability: String,
+    /// the hall or screen of a showing, also read from a `data-hall` or `data-screen` attribute
+    pub showtime_hall: String,
+    /// a city on the cinema list
+    pub cinema_group: String,
+    /// a cinema on the cinema list, within a `cinema_group`
+    pub cinema_option: String,
+    /// the link to the next page of a long schedule, or its "load more" button, by `href` or `data-href`
+    pub next_page: String,
+}
+
+impl Default for Selectors {
+    fn default() -> Self {
+        Selectors {
+            schedule_item: "div.schedule-simple__item".to_string(),
+            title: "h4 a".to_string(),
+            poster: "div.schedule-simple__poster img".to_string(),
+            showtime: "a.schedule-time".to_string(),
+            showtime_start: "span.schedule-time__start".to_string(),
+            showtime_end: "span.schedule-time__end".to_string(),
+            showtime_label: "span.schedule-time__label".to_string(),
+            showtime_availability: "span.schedule-time__availability".to_string(),
+            showtime_hall: "span.schedule-time__hall".to_string(),
+            cinema_group: "select[name=cinemaId] optgroup".to_string(),
+            cinema_option: "option[value]".to_string(),
+            next_page: "a[rel=next], .schedule-simple__more".to_string(),
+        }
+    }
+}
+
+impl Selectors {
+    /// Checks that every selector parses, so a typo is reported when the config is loaded.
+    pub(crate) fn validate(&self) -> Result<(), MonitorError> {
+        for selector in [
+            &self.schedule_item,
+            &self.title,
+            &self.poster,
+            &self.showtime,
+            &self.showtime_start,
+            &self.showtime_end,
+            &self.showtime_label,
+            &self.showtime_availability,
+            &self.showtime_hall,
+            &self.cinema_group,
+            &self.cinema_option,
+            &self.next_page,
+        ] {
+            parse_selector(selector)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Combines the start time of a showtime with the date of the schedule it's listed on.
+pub(crate) fn parse_start(date: &str, start: &str) -> Option<DateTime<Tz>> {
+    let date = NaiveDate::parse_from_str(date, DATE_FORMAT).ok()?;
+    let time = NaiveTime::parse_from_str(start.trim(), TIME_FORMAT).ok()?;
+    let date = if time.hour() < NIGHT_SHOWING_HOUR {
+        date + Duration::days(1)
+    } else {
+        date
+    };
+
+    PATHE_TZ
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+}
+
+pub(crate) fn generate_showtime(
+    time: ElementRef,
+    date: &str,
+    selectors: &Selectors,
+) -> Result<Showtime, MonitorError> {
+    let start = first_text(time, &selectors.showtime_start)?;
+    let end = first_text(time, &selectors.showtime_end)?;
+    let type_name = first_text(time, &selectors.showtime_label)?;
+
+    let link = showtime_link(time)?;
+
+    Ok(Showtime {
+        label: type_name.to_string(),
+        start: start.to_string(),
+        end: end.to_string(),
+        link,
+        availability: showtime_availability(time, selectors),
+        hall: showtime_hall(time, selectors),
+        preview: showtime_is_preview(time, type_name),
+        bookable: showtime_is_bookable(time),
+        cinema: None,
+        starts_at: parse_start(date, start),
+    })
+}
+
+/// Links to the checkout of a showtime when Pathé exposes it, or else to the showtime page.
+pub(crate) fn showtime_link(time: ElementRef) -> Result<String, MonitorError> {
+    let href = match ORDER_LINK_ATTRIBUTES.iter().find_map(|attr| {
+        time.value()
+            .attr(attr)
+            .filter(|href| !href.trim().is_empty())
+    }) {
+        Some(href) => href.trim(),
+        None => required_attr(time, "data-href")?,
+    };
+
+    Ok(absolute_link(href))
+}
+
+/// Resolves a link of the schedule against pathe.nl.
+pub(crate) fn absolute_link(href: &str) -> String {
+    if href.starts_with("http") {
+        href.to_string()
+    } else {
+        format!("https://pathe.nl{}", href)
+    }
+}
+
+/// Whether a showing is a preview or only open to Pathé Unlimited members, from its modifier classes, a
+/// `data-preview` attribute or its label.
+pub(crate) fn showtime_is_preview(time: ElementRef, label: &str) -> bool {
+    let marked = |text: &str| {
+        let text = text.to_lowercase();
+        PREVIEW_MARKERS.iter().any(|marker| text.contains(marker))
+    };
+
+    time.value().classes().any(marked)
+        || time
+            .value()
+            .attr("data-preview")
+            .is_some_and(|preview| preview != "false")
+        || label.split_whitespace().any(marked)
+}
+
+/// Whether tickets for a showing can be bought, Pathé greys out the showings that aren't on sale (yet) through their
+/// modifier classes or a `disabled` state.
+pub(crate) fn showtime_is_bookable(time: ElementRef) -> bool {
+    let element = time.value();
+    let unavailable = |class: &str| {
+        NOT_BOOKABLE_MARKERS
+            .iter()
+            .any(|marker| class.contains(marker))
+            || (class.contains("sold-out") && !class.contains("almost-sold-out"))
+    };
+
+    !(element.classes().any(unavailable)
+        || element.attr("disabled").is_some()
+        || element.attr("aria-disabled") == Some("true")
+        || element.attr("data-bookable") == Some("false"))
+}
+
+/// Reads the hall a showing is in, when the schedule mentions it.
+pub(crate) fn showtime_hall(time: ElementRef, selectors: &Selectors) -> Option<String> {
+    first_text(time, &selectors.showtime_hall)
+        .ok()
+        .or_else(|| {
+            let element = time.value();
+            element
+                .attr("data-hall")
+                .or_else(|| element.attr("data-screen"))
+        })
+        .map(str::trim)
+        .filter(|hall| !hall.is_empty())
+        .map(str::to_string)
+}
+
+/// Reads how full a showing is, from its availability label or its modifier classes.
+pub(crate) fn showtime_availability(time: ElementRef, selectors: &Selectors) -> Option<String> {
+    if let Ok(text) = first_text(time, &selectors.showtime_availability) {
+        if !text.trim().is_empty() {
+            return Some(text.trim().to_string());
+        }
+    }
+
+    let classes: Vec<&str> = time.value().classes().collect();
+    if classes
+        .iter()
+        .any(|class| class.contains("almost-sold-out"))
+    {
+        Some(LOCALE.almost_sold_out().to_string())
+    } else if classes.iter().any(|class| class.contains("sold-out")) {
+        Some(LOCALE.sold_out().to_string())
+    } else {
+        None
+    }
+}
+
+/// Drops the showtimes the schedule lists more than once, keeping the first.
+pub(crate) fn dedupe_showtimes(showtimes: &mut Vec<Showtime>) {
+    let mut seen = HashSet::new();
+    showtimes.retain(|showtime| {
+        let unique = seen.insert((
+            showtime.start.clone(),
+            showtime.end.clone(),
+            showtime.label.clone(),
+            showtime.link.clone(),
+        ));
+        if !unique {
+            trace!(
+                "skipping duplicate showtime {} {}",
+                showtime.label,
+                showtime.start
+            );
+        }
+        unique
+    });
+}
+
+pub fn generate_notification(
+    request: &MovieMonitorRequest,
+    cinema: &CinemaRef,
+    date: &str,
+    selectors: &Selectors,
+    item: ElementRef,
+) -> Result<Notification, MonitorError> {
+    trace!("creating notification for {} on {}", request, date);
+    let time_selector = parse_selector(&selectors.showtime)?;
+
+    let title_element = select_first(item, &selectors.title)?;
+    let title = first_text(item, &selectors.title)?;
+
+    let mut showtimes = vec![];
+    for time in item.select(&time_selector) {
+        match generate_showtime(time, date, selectors) {
+            Ok(showtime) => showtimes.push(showtime),
+            Err(e) => warn!("skipping showtime of '{}': {}", title, e),
+        }
+    }
+
+    dedupe_showtimes(&mut showtimes);
+    // the schedule isn't always in chronological order, showtimes that couldn't be parsed go last
+    showtimes.sort_by_key(|showtime| (showtime.starts_at.is_none(), showtime.starts_at));
+
+    let poster = select_first(item, &selectors.poster)
+        .and_then(|e_thumbnail| required_attr(e_thumbnail, "src"))
+        .map_err(|e| warn!("using placeholder thumbnail for '{}': {}", title, e))
+        .ok();
+    let href = required_attr(title_element, "href")?;
+
+    Ok(scheduled_notification(
+        request,
+        cinema,
+        date,
+        &ScheduledMovie {
+            title: title.to_string(),
+            href: Some(href.to_string()),
+            poster: poster.map(str::to_string),
+            showtimes,
+        },
+    ))
+}
+
+/// Whether a scheduled movie is what the request is after, by the slug in its link or else by its title.
+pub(crate) fn is_requested(request: &MovieMonitorRequest, title: &str, href: Option<&str>) -> bool {
+    let requested = href.is_some_and(|href| request.matches_slug(href)) || request.matches(title);
+    if requested {
+        debug!("'{}' matched {}", title, request);
+    }
+    requested
+}
+
+/// The notification about a scheduled movie that matched the request, with only the showtimes the request allows.
+pub(crate) fn scheduled_notification(
+    request: &MovieMonitorRequest,
+    cinema: &CinemaRef,
+    date: &str,
+    movie: &ScheduledMovie,
+) -> Notification {
+    let title = movie.title.as_str();
+    let showtimes = movie
+        .showtimes
+        .iter()
+        .filter(|showtime| {
+            let allowed = request.allows(showtime);
+            if !allowed {
+                trace!(
+                    "filtered out {} showtime {}",
+                    showtime.label,
+                    showtime.start
+                );
+            }
+            allowed
+        })
+        .cloned()
+        .map(|mut showtime| {
+            if request.cinema.is_multiple() {
+                showtime.cinema = Some(cinema.to_string());
+            }
+            showtime
+        })
+        .collect();
+
+    Notification {
+        movie: request
+            .matching_movie(title)
+            .filter(|movie| movie.trim() != ANY_MOVIE)
+            .unwrap_or(title)
+            .to_string(),
+        title: title.to_string(),
+        date: date.to_string(),
+        cinema: cinema.to_string(),
+        url: match &movie.href {
+            Some(href) => format!("{}#agenda", absolute_link(href)),
+            None => cinema.schedule_url(date),
+        },
+        thumbnail: movie
+            .poster
+            .clone()
+            .unwrap_or_else(|| PLACEHOLDER_THUMBNAIL_URL.to_string()),
+        showtimes,
+        request_id: request.id(),
+        message_template: request.message_template.clone(),
+    }
+}
+
+/// Whether any showtimes of a match are left after the filters of its request.
+pub(crate) fn passes_filters(notification: &Notification) -> bool {
+    let passes = !notification.showtimes.is_empty();
+    if !passes {
+        info!(
+            "No showtimes of '{}' on {} in {} pass the filters",
+            notification.title, notification.date, notification.cinema
+        );
+    }
+    passes
+}
+
+/// What a schedule page holds for a request.
+pub(crate) struct ScheduleScan {
+    /// the matches with showtimes, notified before or not
+    pub(crate) notifications: Vec<Notification>,
+    /// whether an item was skipped because it misses an element the selectors expect
+    pub(crate) incomplete: bool,
+}
+
+/// Finds the scheduled movies that match the request.
+///
+/// Returns `None` when the page has no scheduled items at all, e.g. an empty body or a maintenance page. Kept apart
+/// from `check_response` because the parsed `Html` can't be held across an `.await`.
+pub(crate) fn scan_schedule(
+    request: &MovieMonitorRequest,
+    cinema: &CinemaRef,
+    date: &str,
+    selectors: &Selectors,
+    body: &str,
+) -> Result<Option<ScheduleScan>, MonitorError> {
+    let fragment = Html::parse_fragment(body);
+
+    let scheduled_item_selector = parse_selector(&selectors.schedule_item)?;
+    if fragment.select(&scheduled_item_selector).next().is_none() {
+        return Ok(None);
+    }
+
+    let mut notifications = vec![];
+    let mut incomplete = false;
+
+    for item in fragment.select(&scheduled_item_selector) {
+        let title = match first_text(item, &selectors.title) {
+            Ok(title) => title,
+            Err(e) => {
+                warn!("skipping scheduled item without title: {}", e);
+                incomplete = true;
+                continue;
+            }
+        };
+
+        let href = select_first(item, &selectors.title)
+            .and_then(|title_element| required_attr(title_element, "href"))
+            .ok();
+        if is_requested(request, title, href) {
+            match generate_notification(request, cinema, date, selectors, item) {
+                Ok(notification) if !passes_filters(&notification) => {}
+                Ok(notification) => notifications.push(notification),
+                Err(e) => {
+                    warn!("skipping '{}', failed creating notification: {}", title, e);
+                    incomplete = true;
+                }
+            }
+        }
+    }
+
+    Ok(Some(ScheduleScan {
+        notifications,
+        incomplete,
+    }))
+}
+
+/// Combines the matches of the same movie at different cinemas into one notification, naming every cinema.
+pub(crate) fn merge_cinemas(matches: Vec<Notification>) -> Vec<Notification> {
+    let mut merged: Vec<Notification> = vec![];
+
+    for notification in matches {
+        match merged
+            .iter_mut()
+            .find(|merged| merged.title == notification.title)
+        {
+            Some(merged) => {
+                if !merged
+                    .cinema
+                    .split(", ")
+                    .any(|cinema| cinema == notification.cinema)
+                {
+                    merged.cinema = format!("{}, {}", merged.cinema, notification.cinema);
+                }
+                merged.showtimes.extend(notification.showtimes);
+                merged
+                    .showtimes
+                    .sort_by_key(|showtime| (showtime.starts_at.is_none(), showtime.starts_at));
+            }
+            None => merged.push(notification),
+        }
+    }
+
+    merged
+}
+
+/// The matches that haven't been notified with these showtimes yet, a catch-all request only notifies new titles.
+pub(crate) fn unnotified(
+    request: &MovieMonitorRequest,
+    date: &str,
+    matches: Vec<Notification>,
+) -> Vec<Notification> {
+    matches
+        .into_iter()
+        .filter(|notification| {
+            let title = &notification.title;
+            let seen = request.seen_on(date, title);
+            if request.is_catch_all() && !seen.is_empty() {
+                debug!("Already notified about new movie '{}' on {}", title, date);
+                false
+            } else if seen == MovieMonitorRequest::seen_entries(notification) {
+                info!(
+                    "Already notified about '{}' on {}, showtimes are unchanged",
+                    title, date
+                );
+                false
+            } else {
+                info!(
+                    "{} showtime(s) of '{}' available on {}",
+                    notification.showtimes.len(),
+                    title,
+                    date
+                );
+                true
+            }
+        })
+        .collect()
+}
+
+/// A movie on the schedule with all of its showtimes, regardless of any request.
+#[derive(Clone, Debug)]
+pub struct ScheduledMovie {
+    pub title: String,
+    /// the link to the movie's page, which ends in the slug a request can match with `movie_slug`
+    pub href: Option<String>,
+    pub poster: Option<String>,
+    pub showtimes: Vec<Showtime>,
+}
+
+/// Lists every scheduled movie as Pathé titles it, to find out what a request should match.
+pub fn list_schedule(
+    date: &str,
+    selectors: &Selectors,
+    body: &str,
+) -> Result<Vec<ScheduledMovie>, MonitorError> {
+    let fragment = Html::parse_fragment(body);
+
+    let scheduled_item_selector = parse_selector(&selectors.schedule_item)?;
+    let time_selector = parse_selector(&selectors.showtime)?;
+
+    let mut movies = vec![];
+
+    for item in fragment.select(&scheduled_item_selector) {
+        let title = match first_text(item, &selectors.title) {
+            Ok(title) => title,
+            Err(e) => {
+                warn!("skipping scheduled item without title: {}", e);
+                continue;
+            }
+        };
+
+        let mut showtimes = vec![];
+        for time in item.select(&time_selector) {
+            match generate_showtime(time, date, selectors) {
+                Ok(showtime) => showtimes.push(showtime),
+                Err(e) => warn!("skipping showtime of '{}': {}", title, e),
+            }
+        }
+        dedupe_showtimes(&mut showtimes);
+        showtimes.sort_by_key(|showtime| (showtime.starts_at.is_none(), showtime.starts_at));
+
+        movies.push(ScheduledMovie {
+            title: title.to_string(),
+            href: select_first(item, &selectors.title)
+                .and_then(|title_element| required_attr(title_element, "href"))
+                .map(str::to_string)
+                .ok(),
+            poster: select_first(item, &selectors.poster)
+                .and_then(|poster| required_attr(poster, "src"))
+                .map(str::to_string)
+                .ok(),
+            showtimes,
+        });
+    }
+
+    Ok(movies)
+}
+
+/// `Config::debug_dump_dir`, set by `share_settings` at startup.
+pub(crate) static DEBUG_DUMP_DIR: OnceLock<Option<String>> = OnceLock::new();
+
+/// Writes the body of a schedule to a timestamped file under `DEBUG_DUMP_DIR`, to fix the selectors with.
+pub(crate) fn dump_body(cinema: &CinemaRef, date: &str, body: &str) {
+    let dir = match DEBUG_DUMP_DIR.get().and_then(Option::as_ref) {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    let path = Path::new(dir).join(format!(
+        "{}-{}-{}.html",
+        cinema.id(),
+        date,
+        Utc::now().format("%Y%m%dT%H%M%S%.3f")
+    ));
+    match fs::create_dir_all(dir).and_then(|_| fs::write(&path, body)) {
+        Ok(()) => warn!(
+            "Dumped the schedule of {} on {} to `{}`",
+            cinema,
+            date,
+            path.display()
+        ),
+        Err(e) => warn!("failed dumping the schedule to `{}`: {}", path.display(), e),
+    }
+}
+
+/// What checking a schedule found, before notifying about it.
+pub(crate) struct ScheduleCheck {
+    /// whether any match has showtimes, notified before or not
+    pub(crate) found: bool,
+    /// the matches that still have to be notified
+    pub(crate) notifications: Vec<Notification>,
+}
+
+/// Matches the schedules of every cinema of the request on `date` against it and notifies about removed movies, but
+/// leaves notifying the new matches to the caller, so they can be sent together with those of other requests.
+pub(crate) async fn check_schedule(
+    request: &mut MovieMonitorRequest,
+    date: &str,
+    selectors: &Selectors,
+    notifiers: &[Arc<dyn Notifier>],
+    dry_run: bool,
+    schedules: &[(CinemaRef, FetchedSchedule)],
+) -> Result<ScheduleCheck, MonitorError> {
+    debug!("handling {} response(s) for {}", request, date);
+
+    let mut matches = vec![];
+    let mut scanned = 0;
+    for (cinema, schedule) in schedules {
+        let scan = match schedule {
+            FetchedSchedule::Html(body) => scan_schedule(request, cinema, date, selectors, body)?,
+            FetchedSchedule::Json { movies, .. } => {
+                scan_json_schedule(request, cinema, date, movies)
+            }
+        };
+        let ScheduleScan {
+            notifications,
+            incomplete,
+        } = match scan {
+            Some(scan) => scan,
+            None => {
+                warn!(
+                    "The schedule of {} on {} has no movies at all, Pathé may be down or have changed its markup",
+                    cinema, date
+                );
+                dump_body(cinema, date, schedule.body());
+                SCRAPE_ERRORS_TOTAL
+                    .with_label_values(&[&cinema.to_string()])
+                    .inc();
+                continue;
+            }
+        };
+
+        scanned += 1;
+        if incomplete {
+            dump_body(cinema, date, schedule.body());
+        }
+        matches.extend(notifications);
+    }
+
+    let matches = merge_cinemas(matches);
+    let present: Vec<String> = matches
+        .iter()
+        .map(|notification| notification.title.clone())
+        .collect();
+    let notifications = unnotified(request, date, matches);
+
+    // a movie is only gone once the schedules of all cinemas of the request have been read without it
+    if request.notify_on_removal && scanned == request.cinema.cinemas().len() {
+        for title in request.seen_titles(date) {
+            if present.contains(&title) {
+                continue;
+            }
+
+            info!("'{}' is no longer available on {}", title, date);
+            let text = LOCALE.no_longer_available(&title, date, &request.cinema.to_string());
+            if notify_text(notifiers, &text, dry_run).await {
+                request.forget(date, &title);
+            }
+        }
+    }
+
+    Ok(ScheduleCheck {
+        found: !present.is_empty(),
+        notifications,
+    })
+}
+
+/// Remembers the notifications of the request that were delivered, and disables it when it's a oneshot request.
+pub(crate) fn mark_delivered(
+    request: &mut MovieMonitorRequest,
+    notifications: &[Notification],
+    now: DateTime<FixedOffset>,
+) {
+    for notification in notifications {
+        request.mark_notified(notification, now);
+    }
+
+    if request.oneshot && !notifications.is_empty() {
+        info!("{} request satisfied, disabling", request);
+        request.enabled = false;
+    }
+}
+
+/// Drops the notifications of a request that is in its cooldown. They aren't remembered as notified, so they are sent
+/// after the cooldown if they still apply.
+pub(crate) fn skip_in_cooldown(
+    request: &MovieMonitorRequest,
+    notifications: Vec<Notification>,
+    now: DateTime<FixedOffset>,
+) -> Vec<Notification> {
+    match request.cooldown_until(now) {
+        Some(until) if !notifications.is_empty() => {
+            debug!(
+                "{} is in its cooldown until {}, suppressing {} notification(s)",
+                request,
+                until.format("%d-%m-%Y %H:%M"),
+                notifications.len()
+            );
+            vec![]
+        }
+        _ => notifications,
+    }
+}
+
+pub(crate) fn not_delivered(notifications: &[Notification]) -> MonitorError {
+    MonitorError::NotDelivered(
+        notifications
+            .iter()
+            .map(|notification| format!("'{}'", notification.title))
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+/// Checks `body` as the schedule of the (first) cinema of the request on `date` and notifies about the matches.
+pub async fn check_response(
+    request: &mut MovieMonitorRequest,
+    date: &str,
+    selectors: &Selectors,
+    notifiers: &[Arc<dyn Notifier>],
+    dry_run: bool,
+    now: DateTime<FixedOffset>,
+    body: &str,
+) -> Result<bool, MonitorError> {
+    let schedules = match request.cinema.cinemas().first() {
+        Some(cinema) => vec![(
+            cinema.clone(),
+            FetchedSchedule::read(date, body.to_string())?,
+        )],
+        None => vec![],
+    };
+    let ScheduleCheck {
+        found,
+        notifications,
+    } = check_schedule(request, date, selectors, notifiers, dry_run, &schedules).await?;
+    let notifications = skip_in_cooldown(request, notifications, now);
+
+    if !notifications.is_empty() {
+        if notify(notifiers, &notifications, dry_run).await {
+            mark_delivered(request, &notifications, now);
+        } else if !dry_run {
+            return Err(not_delivered(&notifications));
+        }
+    }
+
+    Ok(found)
+}
+
+pub(crate) fn is_retryable(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => status.is_server_error(),
+        None => true,
+    }
+}
+
+/// The next page of the schedule on `url`, if it links to one, relative links are resolved against `url`.
+pub(crate) fn next_page_url(
+    url: &str,
+    selectors: &Selectors,
+    body: &str,
+) -> Result<Option<String>, MonitorError> {
+    let fragment = Html::parse_fragment(body);
+    let href = match fragment
+        .select(&parse_selector(&selectors.next_page)?)
+        .find_map(|next| {
+            ["href", "data-href"].iter().find_map(|attr| {
+                next.value()
+                    .attr(attr)
+                    .map(str::trim)
+                    .filter(|href| !href.is_empty() && !href.starts_with('#'))
+            })
+        }) {
+        Some(href) => href,
+        None => return Ok(None),
+    };
+
+    Ok(reqwest::Url::parse(url)
+        .and_then(|url| url.join(href))
+        .map(|next| next.to_string())
+        .map_err(|e| warn!("ignoring next page `{}` of {}: {}", href, url, e))
+        .ok())
+}
+
+/// Fetches the schedule on `url` and the pages it continues on, up to `MAX_SCHEDULE_PAGES`, joined as a single body.
+pub(crate) async fn fetch_schedule<F, Fut>(
+    url: &str,
+    selectors: &Selectors,
+    fetch: F,
+) -> Result<String, MonitorError>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<String, MonitorError>>,
+{
+    let mut urls = vec![url.to_string()];
+    let mut pages = vec![fetch(url.to_string()).await?];
+
+    while let Some(next) = next_page_url(urls.last().unwrap(), selectors, pages.last().unwrap())? {
+        if urls.contains(&next) {
+            debug!("{} links back to `{}`, not following it", url, next);
+            break;
+        }
+        if pages.len() >= MAX_SCHEDULE_PAGES {
+            warn!(
+                "{} continues after {} pages, ignoring the rest",
+                url, MAX_SCHEDULE_PAGES
+            );
+            break;
+        }
+
+        debug!("following the schedule on {} to `{}`", url, next);
+        pages.push(fetch(next.clone()).await?);
+        urls.push(next);
+    }
+
+    Ok(pages.join("\n"))
+}
+
+/// Fetches `url`, retrying transient failures with an exponential backoff (1s, 2s, 4s, ...).
+pub(crate) async fn fetch_with_retries(
+    client: &Client,
+    url: &str,
+    max_retries: u32,
+) -> Result<String, MonitorError> {
+    let mut attempt = 0;
+
+    loop {
+        // an error page can look like a schedule without any movies, so only a success is scraped
+        let result = match client.get(url).send().await {
+            Ok(res) if !res.status().is_success() => {
+                warn!("Pathé responded with {} for {}", res.status(), url);
+                res.error_for_status().map(|_| String::new())
+            }
+            Ok(res) => res.text().await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(body) => return Ok(body),
+            Err(e) if attempt < max_retries && is_retryable(&e) => {
+                let delay = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                attempt += 1;
+                warn!(
+                    "error calling {}: {}, retry {}/{} in {:?}",
+                    url, e, attempt, max_retries, delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Whether Pathé is called, exposed on `/status`.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Pathé is called as usual
+    #[default]
+    Closed,
+    /// Pathé failed too often in a row and isn't called until the cooldown has passed
+    Open,
+    /// the cooldown has passed, a single request probes whether Pathé is back
+    HalfOpen,
+}
+
+/// Stops calling Pathé for a while after `threshold` consecutive failures, a `threshold` of 0 never stops.
+pub(crate) struct CircuitBreaker {
+    pub(crate) threshold: u32,
+    pub(crate) cooldown: std::time::Duration,
+    pub(crate) failures: u32,
+    pub(crate) opened: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(threshold: u32, cooldown: std::time::Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            failures: 0,
+            opened: None,
+        }
+    }
+
+    pub(crate) fn state(&self) -> BreakerState {
+        match self.opened {
+            None => BreakerState::Closed,
+            Some(opened) if opened.elapsed() < self.cooldown => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    /// How long Pathé won't be called anymore, if the breaker is open.
+    pub(crate) fn remaining(&self) -> Option<std::time::Duration> {
+        self.opened
+            .and_then(|opened| self.cooldown.checked_sub(opened.elapsed()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    pub(crate) fn record_success(&mut self) {
+        if self.opened.is_some() {
+            info!("Pathé responded again, resuming all checks");
+        }
+        self.failures = 0;
+        self.opened = None;
+    }
+
+    pub(crate) fn record_failure(&mut self) {
+        self.failures += 1;
+        match self.state() {
+            BreakerState::HalfOpen => {
+                warn!(
+                    "Pathé is still failing, backing off for another {:?}",
+                    self.cooldown
+                );
+                self.opened = Some(Instant::now());
+            }
+            BreakerState::Closed if self.threshold > 0 && self.failures >= self.threshold => {
+                warn!(
+                    "Pathé failed {} times in a row, backing off for {:?}",
+                    self.failures, self.cooldown
+                );
+                self.opened = Some(Instant::now());
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Fetches Pathé pages, retrying failures and keeping a minimum delay between the start of two requests.
+pub(crate) struct Fetcher {
+    pub(crate) client: Client,
+    pub(crate) max_retries: u32,
+    pub(crate) delay: std::time::Duration,
+    /// checking a request that takes longer is warned about, zero never warns
+    pub(crate) slow_request: std::time::Duration,
+    /// checking a request that takes longer is abandoned, zero never abandons
+    pub(crate) check_timeout: std::time::Duration,
+    /// where the schedules are read from, and the JSON schedules with `json` or `auto`
+    pub(crate) source: ScheduleSource,
+    pub(crate) api_url: String,
+    /// shared by the concurrent checks, so only the start of the requests is serialized
+    pub(crate) last_request: tokio::sync::Mutex<Option<Instant>>,
+    pub(crate) breaker: Mutex<CircuitBreaker>,
+}
+
+impl Fetcher {
+    pub(crate) async fn fetch(&self, url: &str) -> Result<String, MonitorError> {
+        if let Some(remaining) = self.breaker.lock().unwrap().remaining() {
+            return Err(MonitorError::CircuitOpen(remaining.as_secs()));
+        }
+
+        {
+            let mut last_request = self.last_request.lock().await;
+            if let Some(last_request) = *last_request {
+                let elapsed = last_request.elapsed();
+                if elapsed < self.delay {
+                    trace!("waiting {:?} before calling {}", self.delay - elapsed, url);
+                    tokio::time::sleep(self.delay - elapsed).await;
+                } else {
+                    debug!(
+                        "previous request was {:?} ago, longer than the {:?} delay, not waiting",
+                        elapsed, self.delay
+                    );
+                }
+            }
+
+            *last_request = Some(Instant::now());
+        }
+
+        let result = fetch_with_retries(&self.client, url, self.max_retries).await;
+        let mut breaker = self.breaker.lock().unwrap();
+        match &result {
+            Ok(_) => breaker.record_success(),
+            Err(MonitorError::Http(e)) if is_retryable(e) => breaker.record_failure(),
+            Err(_) => (),
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::Utc;
+
+    use crate::api::DEFAULT_SCHEDULE_API_URL;
+    use crate::cinema::{Cinema, CinemaSpec};
+    use crate::notifications::{
+        email_html, telegram_text, DiscordNotification, DiscordNotifier, TextFormat,
+    };
+    use crate::request::MovieSpec;
+    use crate::testing::{
+        check, check_with_selectors, dry_check, now, request, CapturingNotifier, SCHEDULE,
+        SPUIMARKT,
+    };
+
+    const SCHEDULE_FIXTURE: &str = include_str!("../tests/fixtures/schedule.html");
+    const FIVE_SHOWTIMES_FIXTURE: &str =
+        include_str!("../tests/fixtures/schedule_five_showtimes.html");
+
+    /// Serves `fixture` as the schedule of `request.date` and runs it through `check_response`.
+    async fn check_against_mock_schedule(
+        server: &mut mockito::Server,
+        request: &mut MovieMonitorRequest,
+        notifiers: &[Arc<dyn Notifier>],
+        fixture: &str,
+    ) -> bool {
+        let schedule = server
+            .mock("GET", "/cinema/schedules")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("cinemaId".into(), "13".into()),
+                mockito::Matcher::UrlEncoded("date".into(), "19-08-2021".into()),
+            ]))
+            .with_body(fixture)
+            .create_async()
+            .await;
+
+        let url = request.cinema.cinemas()[0]
+            .schedule_url("19-08-2021")
+            .replace("https://www.pathe.nl", &server.url());
+        let body = fetch_with_retries(&Client::new(), &url, 0).await.unwrap();
+        schedule.assert_async().await;
+
+        check_response(
+            request,
+            "19-08-2021",
+            &Selectors::default(),
+            notifiers,
+            false,
+            now(),
+            &body,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn nothing_is_notified_in_the_cooldown() {
+        let now = now();
+        let mut request = request();
+        request.cooldown_minutes = Some(60);
+        request.last_notified = Some(now - chrono::Duration::minutes(30));
+        assert_eq!(check(&mut request, now, SCHEDULE).await, (true, 0));
+
+        let later = now + chrono::Duration::minutes(31);
+        assert_eq!(check(&mut request, later, SCHEDULE).await, (true, 1));
+        assert_eq!(request.last_notified, Some(later));
+        assert!(request.cooldown_until(later).is_some());
+    }
+
+    #[tokio::test]
+    async fn unchanged_showtimes_are_notified_once() {
+        let now = now();
+        let mut request = request();
+
+        assert_eq!(check(&mut request, now, SCHEDULE).await, (true, 1));
+        assert_eq!(check(&mut request, now, SCHEDULE).await, (true, 0));
+
+        assert_eq!(request.last_notified, Some(now));
+        assert_eq!(
+            request.seen_showtimes,
+            vec![
+                "19-08-2021 | The Green Knight | NL 14:30 - 16:40",
+                "19-08-2021 | The Green Knight | OV 19:00 - 21:10"
+            ]
+        );
+
+        let config: MovieMonitorRequest =
+            serde_json::from_value(json!(request)).expect("written back request is readable");
+        assert_eq!(config.seen_showtimes, request.seen_showtimes);
+    }
+
+    #[tokio::test]
+    async fn removed_movies_are_notified_once() {
+        let now = now();
+        let mut request = request();
+        let without_movie = SCHEDULE.replace("The Green Knight", "Dune: Part Two");
+
+        assert_eq!(check(&mut request, now, SCHEDULE).await, (true, 1));
+        assert_eq!(check(&mut request, now, &without_movie).await, (false, 0));
+        assert_eq!(request.seen_titles("19-08-2021"), vec!["The Green Knight"]);
+
+        request.notify_on_removal = true;
+        assert_eq!(check(&mut request, now, &without_movie).await, (false, 1));
+        assert_eq!(check(&mut request, now, &without_movie).await, (false, 0));
+        assert!(request.seen_showtimes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_schedule_is_not_mistaken_for_a_missing_movie() {
+        let now = now();
+        let selectors = Selectors::default();
+        let mut request = request();
+        request.notify_on_removal = true;
+
+        assert!(
+            scan_schedule(&request, &SPUIMARKT, "19-08-2021", &selectors, "")
+                .unwrap()
+                .is_none()
+        );
+        let scan = scan_schedule(
+            &request,
+            &SPUIMARKT,
+            "19-08-2021",
+            &selectors,
+            &SCHEDULE.replace("The Green Knight", "Dune: Part Two"),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(scan.notifications.is_empty());
+
+        assert_eq!(check(&mut request, now, SCHEDULE).await, (true, 1));
+        let errors = SCRAPE_ERRORS_TOTAL.with_label_values(&["Pathé Spuimarkt"]);
+        let before = errors.get();
+        let maintenance = "<html><body><h1>Onderhoud</h1></body></html>";
+        assert_eq!(check(&mut request, now, maintenance).await, (false, 0));
+        assert!(errors.get() > before);
+        assert_eq!(request.seen_titles("19-08-2021"), vec!["The Green Knight"]);
+    }
+
+    #[tokio::test]
+    async fn undelivered_notifications_fail_the_check() {
+        let notifiers: Vec<Arc<dyn Notifier>> = vec![Arc::new(DiscordNotifier {
+            client: Client::new(),
+            webhook_url: "http://127.0.0.1:9/webhook".to_string(),
+        })];
+        let mut request = request();
+
+        let result = check_response(
+            &mut request,
+            "19-08-2021",
+            &Selectors::default(),
+            &notifiers,
+            false,
+            now(),
+            SCHEDULE,
+        )
+        .await;
+        assert!(matches!(result, Err(MonitorError::NotDelivered(_))));
+        assert!(request.seen_showtimes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dry_run_sends_and_remembers_nothing() {
+        let mut request = request();
+
+        assert_eq!(dry_check(&mut request, now(), SCHEDULE).await, (true, 0));
+        assert!(request.seen_showtimes.is_empty());
+        assert!(request.last_notified.is_none());
+    }
+
+    #[tokio::test]
+    async fn oneshot_requests_are_disabled_once_notified() {
+        let mut request = request();
+        request.oneshot = true;
+
+        assert_eq!(dry_check(&mut request, now(), SCHEDULE).await, (true, 0));
+        assert!(request.enabled);
+        assert_eq!(check(&mut request, now(), SCHEDULE).await, (true, 1));
+        assert!(!request.enabled);
+    }
+
+    #[tokio::test]
+    async fn mock_schedule_is_posted_to_discord() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "content": "Er zijn tickets beschikbaar voor '**The Green Knight**' op **19-08-2021** in **Pathé Spuimarkt**, 2 voorstellingen beschikbaar."
+            })))
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let captured = Arc::new(CapturingNotifier::default());
+        let notifiers: Vec<Arc<dyn Notifier>> = vec![
+            Arc::new(DiscordNotifier {
+                client: Client::new(),
+                webhook_url: format!("{}/webhook", server.url()),
+            }),
+            captured.clone(),
+        ];
+        let mut request = request();
+
+        assert!(
+            check_against_mock_schedule(&mut server, &mut request, &notifiers, SCHEDULE_FIXTURE)
+                .await
+        );
+        webhook.assert_async().await;
+
+        let notifications = captured.0.lock().unwrap();
+        let discord =
+            &DiscordNotification::messages(&notifications[..], false, TextFormat::Markdown)[0];
+        assert_eq!(discord.embeds.len(), 1);
+        let embed = &discord.embeds[0];
+        assert_eq!(embed.title, "The Green Knight");
+        assert_eq!(
+            embed.url,
+            "https://pathe.nl/film/12345/the-green-knight#agenda"
+        );
+        assert_eq!(
+            embed.thumbnail.url,
+            "https://pathe.nl/thumb/the-green-knight.jpg"
+        );
+        assert_eq!(embed.fields.len(), 2);
+        assert!(embed.footer.text.starts_with(&format!(
+            "Generated by pathe-monitor {} ({}) | Pathé Spuimarkt | 19-08-2021 | #{}",
+            env!("CARGO_PKG_VERSION"),
+            env!("GIT_COMMIT"),
+            request.id()
+        )));
+        assert_eq!(
+            embed.fields[1].value,
+            "[19:00 - 21:10](https://pathe.nl/tickets/vertoning/1002) ⚠️ bijna uitverkocht"
+        );
+    }
+
+    #[tokio::test]
+    async fn misaligned_showtimes_are_padded_with_popcorn() {
+        let mut server = mockito::Server::new_async().await;
+        let captured = Arc::new(CapturingNotifier::default());
+        let notifiers: Vec<Arc<dyn Notifier>> = vec![captured.clone()];
+        let mut request = request();
+
+        assert!(
+            check_against_mock_schedule(
+                &mut server,
+                &mut request,
+                &notifiers,
+                FIVE_SHOWTIMES_FIXTURE
+            )
+            .await
+        );
+
+        let notifications = captured.0.lock().unwrap();
+        let discord =
+            &DiscordNotification::messages(&notifications[..], true, TextFormat::Markdown)[0];
+        assert_eq!(discord.embeds.len(), 1);
+        let fields = &discord.embeds[0].fields;
+        assert_eq!(fields.len(), 6);
+        assert_eq!(fields[5].name, ":rooster:");
+        assert_eq!(fields[5].value, ":popcorn:");
+        assert!(discord.content.ends_with(", 5 voorstellingen beschikbaar."));
+
+        let unpadded =
+            &DiscordNotification::messages(&notifications[..], false, TextFormat::Markdown)[0];
+        assert_eq!(unpadded.embeds[0].fields.len(), 5);
+        assert_eq!(
+            email_html(&notifications[0], TextFormat::Html)
+                .matches("<li>")
+                .count(),
+            5
+        );
+        assert!(!telegram_text(&notifications[0], TextFormat::Markdown).contains(":popcorn:"));
+    }
+
+    #[test]
+    fn many_showtimes_are_split_within_discord_limits() {
+        let times: String = (0..40)
+            .map(|i| {
+                format!(
+                    r#"<a class="schedule-time" data-href="/tickets/vertoning/{id}">
+                        <span class="schedule-time__start">{hour:02}:{minute:02}</span>
+                        <span class="schedule-time__end">23:59</span>
+                        <span class="schedule-time__label">OV IMAX Dolby Atmos Laser {label}</span>
+                    </a>"#,
+                    id = 3000 + i,
+                    hour = 10 + i / 4,
+                    minute = i % 4 * 15,
+                    label = "x".repeat(300)
+                )
+            })
+            .collect();
+        let body = SCHEDULE.replacen(
+            r#"<a class="schedule-time" data-href="/tickets/1">"#,
+            &format!(
+                r#"{}<a class="schedule-time" data-href="/tickets/1">"#,
+                times
+            ),
+            1,
+        );
+        let notification = scan_schedule(
+            &request(),
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            &body,
+        )
+        .unwrap()
+        .unwrap()
+        .notifications
+        .remove(0);
+        assert_eq!(notification.showtimes.len(), 42);
+        let notifications = vec![notification; 6];
+
+        let messages = DiscordNotification::messages(&notifications, true, TextFormat::Markdown);
+        assert!(messages.len() > 1);
+        for message in &messages {
+            assert!(message.embeds.len() <= DiscordNotifier::MAX_EMBEDS);
+            assert!(message.length() <= DiscordNotifier::MAX_CHARACTERS);
+            assert!(message.content.chars().count() <= DiscordNotifier::MAX_CONTENT);
+            for embed in &message.embeds {
+                assert!(embed.fields.len() <= DiscordNotifier::MAX_FIELDS);
+                assert!(embed.title.chars().count() <= DiscordNotifier::MAX_TITLE);
+                assert!(embed.fields.iter().all(|field| field.name.chars().count()
+                    <= DiscordNotifier::MAX_FIELD_NAME
+                    && field.value.chars().count() <= DiscordNotifier::MAX_FIELD_VALUE));
+            }
+        }
+
+        let embeds: Vec<_> = messages
+            .iter()
+            .flat_map(|message| &message.embeds)
+            .collect();
+        let showtimes = embeds
+            .iter()
+            .flat_map(|embed| &embed.fields)
+            .filter(|field| field.value != ":popcorn:")
+            .count();
+        assert_eq!(showtimes, 6 * 42);
+        assert!(embeds[0].title.starts_with("The Green Knight (1/"));
+        assert!(embeds[0].fields[0].name.ends_with('…'));
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message.content.lines().count())
+                .sum::<usize>(),
+            6
+        );
+    }
+
+    #[tokio::test]
+    async fn showtimes_are_filtered_by_version() {
+        let mut request = request();
+
+        request.versions = Some(vec!["ov".to_string()]);
+        let item = Html::parse_fragment(SCHEDULE);
+        let item = item
+            .select(&parse_selector(&Selectors::default().schedule_item).unwrap())
+            .next()
+            .unwrap();
+        let notification = generate_notification(
+            &request,
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            item,
+        )
+        .unwrap();
+        assert_eq!(notification.showtimes.len(), 1);
+        assert_eq!(notification.showtimes[0].label, "OV");
+
+        request.versions = Some(vec!["3D".to_string()]);
+        assert_eq!(check(&mut request, now(), SCHEDULE).await, (false, 0));
+    }
+
+    #[test]
+    fn order_links_are_preferred_over_the_showtime_page() {
+        let html = Html::parse_fragment(
+            r#"
+            <a class="schedule-time" data-href="/tickets/1" data-order-href="/tickets/1/stoelen">
+                <span class="schedule-time__start">19:00</span>
+            </a>
+            <a class="schedule-time" data-href="/tickets/2" data-order-url="https://tickets.pathe.nl/2">
+                <span class="schedule-time__start">21:00</span>
+            </a>
+            <a class="schedule-time" data-href="/tickets/3" data-order-href="">
+                <span class="schedule-time__start">23:00</span>
+            </a>
+        "#,
+        );
+        let links: Vec<String> = html
+            .select(&parse_selector("a.schedule-time").unwrap())
+            .map(|time| showtime_link(time).unwrap())
+            .collect();
+
+        assert_eq!(
+            links,
+            vec![
+                "https://pathe.nl/tickets/1/stoelen",
+                "https://tickets.pathe.nl/2",
+                "https://pathe.nl/tickets/3"
+            ]
+        );
+    }
+
+    #[test]
+    fn showtime_availability_is_parsed() {
+        let html = Html::parse_fragment(
+            r#"
+            <a class="schedule-time schedule-time--almost-sold-out" data-href="/tickets/1">
+                <span class="schedule-time__start">19:00</span>
+                <span class="schedule-time__end">21:10</span>
+                <span class="schedule-time__label">OV</span>
+            </a>
+        "#,
+        );
+        let time = html
+            .select(&parse_selector("a.schedule-time").unwrap())
+            .next()
+            .unwrap();
+
+        let showtime = generate_showtime(time, "19-08-2021", &Selectors::default()).unwrap();
+        assert_eq!(showtime.availability.as_deref(), Some("bijna uitverkocht"));
+        assert_eq!(showtime.availability_note(), " ⚠️ bijna uitverkocht");
+
+        let schedule = Html::parse_fragment(SCHEDULE);
+        let time = schedule
+            .select(&parse_selector("a.schedule-time").unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(
+            generate_showtime(time, "19-08-2021", &Selectors::default())
+                .unwrap()
+                .availability,
+            None
+        );
+    }
+
+    #[test]
+    fn catch_all_requests_notify_new_titles_once() {
+        let mut request = request();
+        request.movie = MovieSpec::Single("*".to_string());
+        assert_eq!(
+            request.to_string(),
+            "elke nieuwe film op 19-08-2021 in Pathé Spuimarkt"
+        );
+
+        let scan = scan_schedule(
+            &request,
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            SCHEDULE,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(scan.notifications.len(), 1);
+        assert_eq!(scan.notifications[0].title, "The Green Knight");
+        assert_eq!(scan.notifications[0].movie, "The Green Knight");
+        mark_delivered(&mut request, &scan.notifications, Utc::now().into());
+
+        // new showtimes of a notified movie aren't news
+        let body = format!(
+            "{}{}",
+            SCHEDULE.replace("19:00", "20:00"),
+            SCHEDULE.replace("The Green Knight", "Dune")
+        );
+        let scan = scan_schedule(
+            &request,
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            &body,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            unnotified(&request, "19-08-2021", scan.notifications)
+                .iter()
+                .map(|notification| notification.title.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Dune"]
+        );
+    }
+
+    #[tokio::test]
+    async fn showtimes_at_several_cinemas_are_notified_together() {
+        let mut request = request();
+        request.cinema = CinemaSpec::List(vec![SPUIMARKT, CinemaRef::Known(Cinema::Delft)]);
+        let schedules = vec![
+            (SPUIMARKT, FetchedSchedule::Html(SCHEDULE.to_string())),
+            (
+                CinemaRef::Known(Cinema::Delft),
+                FetchedSchedule::Html(SCHEDULE.replace("14:30", "17:45")),
+            ),
+        ];
+
+        let check = check_schedule(
+            &mut request,
+            "19-08-2021",
+            &Selectors::default(),
+            &[],
+            false,
+            &schedules,
+        )
+        .await
+        .unwrap();
+        assert!(check.found);
+        assert_eq!(check.notifications.len(), 1);
+        let notification = &check.notifications[0];
+        assert_eq!(notification.cinema, "Pathé Spuimarkt, Pathé Delft");
+        assert_eq!(
+            notification
+                .showtimes
+                .iter()
+                .map(|showtime| format!("{} {}", showtime.start, showtime.display_label()))
+                .collect::<Vec<_>>(),
+            vec![
+                "14:30 NL in Pathé Spuimarkt",
+                "17:45 NL in Pathé Delft",
+                "19:00 OV in Pathé Spuimarkt",
+                "19:00 OV in Pathé Delft",
+            ]
+        );
+
+        mark_delivered(&mut request, &check.notifications, Utc::now().into());
+        let check = check_schedule(
+            &mut request,
+            "19-08-2021",
+            &Selectors::default(),
+            &[],
+            false,
+            &schedules,
+        )
+        .await
+        .unwrap();
+        assert!(check.found && check.notifications.is_empty());
+
+        // an empty schedule of one cinema doesn't hide the matches at the other
+        request.seen_showtimes.clear();
+        let schedules = vec![
+            (SPUIMARKT, FetchedSchedule::Html(SCHEDULE.to_string())),
+            (
+                CinemaRef::Known(Cinema::Delft),
+                FetchedSchedule::Html(String::new()),
+            ),
+        ];
+        let check = check_schedule(
+            &mut request,
+            "19-08-2021",
+            &Selectors::default(),
+            &[],
+            false,
+            &schedules,
+        )
+        .await
+        .unwrap();
+        assert!(check.found);
+        assert_eq!(check.notifications[0].cinema, "Pathé Spuimarkt");
+    }
+
+    #[test]
+    fn previews_are_marked_and_can_be_skipped() {
+        let body = SCHEDULE.replacen(
+            r#"class="schedule-time""#,
+            r#"class="schedule-time schedule-time--unlimited""#,
+            1,
+        );
+        let scan = scan_schedule(
+            &request(),
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            &body,
+        )
+        .unwrap()
+        .unwrap();
+        let showtimes = &scan.notifications[0].showtimes;
+        assert_eq!(
+            showtimes
+                .iter()
+                .map(|showtime| (showtime.display_label(), showtime.preview))
+                .collect::<Vec<_>>(),
+            vec![
+                ("NL".to_string(), false),
+                ("OV (voorpremière)".to_string(), true)
+            ]
+        );
+
+        let mut request = request();
+        request.include_previews = false;
+        let scan = scan_schedule(
+            &request,
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            &body,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(scan
+            .notifications
+            .iter()
+            .flat_map(|notification| &notification.showtimes)
+            .all(|showtime| !showtime.preview));
+    }
+
+    #[test]
+    fn unbookable_showtimes_are_skipped_when_required() {
+        let body = SCHEDULE
+            .replacen(
+                r#"class="schedule-time""#,
+                r#"class="schedule-time schedule-time--disabled""#,
+                1,
+            )
+            .replacen(
+                r#"class="schedule-time" data-href="/tickets/2""#,
+                r#"class="schedule-time schedule-time--almost-sold-out" data-href="/tickets/2""#,
+                1,
+            );
+        let scan = |request: &MovieMonitorRequest, body: &str| {
+            scan_schedule(
+                request,
+                &SPUIMARKT,
+                "19-08-2021",
+                &Selectors::default(),
+                body,
+            )
+            .unwrap()
+            .unwrap()
+            .notifications
+        };
+
+        let mut request = request();
+        let notifications = scan(&request, &body);
+        assert_eq!(
+            notifications[0]
+                .showtimes
+                .iter()
+                .map(|showtime| (showtime.start.as_str(), showtime.bookable))
+                .collect::<Vec<_>>(),
+            vec![("14:30", true), ("19:00", false)]
+        );
+
+        request.require_bookable = true;
+        let notifications = scan(&request, &body);
+        assert_eq!(notifications[0].showtimes.len(), 1);
+        assert_eq!(notifications[0].showtimes[0].start, "14:30");
+
+        let body = body.replacen(
+            r#"schedule-time--almost-sold-out" data-href"#,
+            r#"schedule-time--sold-out" data-href"#,
+            1,
+        );
+        assert!(scan(&request, &body).is_empty());
+    }
+
+    #[test]
+    fn halls_are_read_and_filtered_on() {
+        let body = SCHEDULE
+            .replacen(
+                r#"<span class="schedule-time__label">OV</span>"#,
+                r#"<span class="schedule-time__label">OV</span><span class="schedule-time__hall">Zaal 6</span>"#,
+                1,
+            )
+            .replacen(
+                r#"data-href="/tickets/2""#,
+                r#"data-href="/tickets/2" data-screen="Zaal 11""#,
+                1,
+            );
+        let scan = |request: &MovieMonitorRequest, body: &str| {
+            scan_schedule(
+                request,
+                &SPUIMARKT,
+                "19-08-2021",
+                &Selectors::default(),
+                body,
+            )
+            .unwrap()
+            .unwrap()
+            .notifications
+        };
+
+        let mut request = request();
+        request.halls = Some(vec!["zaal 6".to_string()]);
+        let notifications = scan(&request, &body);
+        assert_eq!(notifications[0].showtimes.len(), 1);
+        assert_eq!(notifications[0].showtimes[0].display_label(), "OV, Zaal 6");
+
+        request.halls = Some(vec!["Zaal 11".to_string()]);
+        let notifications = scan(&request, &body);
+        assert_eq!(
+            notifications[0].showtimes[0].hall.as_deref(),
+            Some("Zaal 11")
+        );
+
+        // without halls on the schedule the filter keeps every showtime
+        assert_eq!(scan(&request, SCHEDULE)[0].showtimes.len(), 2);
+    }
+
+    #[test]
+    fn duplicate_showtimes_are_listed_once() {
+        let showtime = r#"<a class="schedule-time" data-href="/tickets/1">"#;
+        let body = SCHEDULE.replacen(
+            showtime,
+            &format!(
+                r#"{}
+            <span class="schedule-time__start">19:00</span>
+            <span class="schedule-time__end">21:10</span>
+            <span class="schedule-time__label">OV</span>
+        </a>
+        {}"#,
+                showtime, showtime
+            ),
+            1,
+        );
+        let scan = scan_schedule(
+            &request(),
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            &body,
+        )
+        .unwrap()
+        .unwrap();
+
+        let starts: Vec<&str> = scan.notifications[0]
+            .showtimes
+            .iter()
+            .map(|showtime| showtime.start.as_str())
+            .collect();
+        assert_eq!(starts, vec!["14:30", "19:00"]);
+        assert_eq!(
+            DiscordNotification::messages(&scan.notifications, true, TextFormat::Markdown)[0]
+                .embeds[0]
+                .fields
+                .len(),
+            2
+        );
+
+        let movies = list_schedule("19-08-2021", &Selectors::default(), &body).unwrap();
+        assert_eq!(movies[0].showtimes.len(), 2);
+    }
+
+    #[test]
+    fn showtimes_are_sorted_chronologically() {
+        let body = SCHEDULE.replace("19:00", "00:15");
+        let scan = scan_schedule(
+            &request(),
+            &SPUIMARKT,
+            "19-08-2021",
+            &Selectors::default(),
+            &body,
+        )
+        .unwrap()
+        .unwrap();
+
+        let showtimes = &scan.notifications[0].showtimes;
+        let starts: Vec<&str> = showtimes.iter().map(|s| s.start.as_str()).collect();
+        assert_eq!(starts, vec!["14:30", "00:15"]);
+        assert_eq!(
+            showtimes[1].starts_at,
+            Some(PATHE_TZ.ymd(2021, 8, 20).and_hms(0, 15, 0))
+        );
+        assert_eq!(parse_start("19-08-2021", "later"), None);
+    }
+
+    #[test]
+    fn schedule_is_listed_without_a_request() {
+        let body = format!(
+            "{}{}",
+            SCHEDULE,
+            SCHEDULE.replace("The Green Knight", "Dune: Part Two")
+        );
+        let movies = list_schedule("19-08-2021", &Selectors::default(), &body).unwrap();
+
+        let titles: Vec<&str> = movies.iter().map(|movie| movie.title.as_str()).collect();
+        assert_eq!(titles, vec!["The Green Knight", "Dune: Part Two"]);
+        assert_eq!(movies[1].showtimes.len(), 2);
+        assert_eq!(movies[1].showtimes[0].start, "14:30");
+        assert_eq!(
+            movies[0].href.as_deref(),
+            Some("/film/12345/the-green-knight")
+        );
+    }
+
+    #[test]
+    fn items_missing_elements_mark_the_scan_incomplete() {
+        let scan = |body: &str| {
+            scan_schedule(
+                &request(),
+                &SPUIMARKT,
+                "19-08-2021",
+                &Selectors::default(),
+                body,
+            )
+            .unwrap()
+            .unwrap()
+            .incomplete
+        };
+
+        assert!(!scan(SCHEDULE));
+        assert!(scan(&SCHEDULE.replace("h4", "h3")));
+        assert!(scan(&SCHEDULE.replace("href=\"/film", "data-film=\"/film")));
+    }
+
+    #[test]
+    fn movies_are_matched_by_slug_before_title() {
+        let mut request = request();
+        request.movie = MovieSpec::Single("De Groene Ridder".to_string());
+        let scan = |request: &MovieMonitorRequest| {
+            scan_schedule(
+                request,
+                &SPUIMARKT,
+                "19-08-2021",
+                &Selectors::default(),
+                SCHEDULE,
+            )
+            .unwrap()
+            .unwrap()
+            .notifications
+            .into_iter()
+            .map(|notification| notification.title)
+            .collect::<Vec<_>>()
+        };
+        assert!(scan(&request).is_empty());
+
+        request.movie_slug = Some("12345/the-green-knight".to_string());
+        assert_eq!(scan(&request), vec!["The Green Knight"]);
+    }
+
+    #[tokio::test]
+    async fn selectors_can_be_overridden() {
+        let mut request = request();
+
+        let selectors: Selectors = serde_json::from_value(json!({ "title": "h3 a" })).unwrap();
+        assert_eq!(selectors.schedule_item, Selectors::default().schedule_item);
+
+        let body = SCHEDULE.replace("h4", "h3");
+        assert_eq!(check(&mut request, now(), &body).await, (false, 0));
+        assert_eq!(
+            check_with_selectors(&mut request, &selectors, now(), &body).await,
+            (true, 1)
+        );
+
+        let broken: Selectors = serde_json::from_value(json!({ "showtime": "a[" })).unwrap();
+        assert!(broken.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn multiple_movies_are_notified_together() {
+        let mut request = request();
+        request.movie = MovieSpec::List(vec!["Green Knight".to_string(), "Dune".to_string()]);
+
+        let body = format!(
+            "{}{}",
+            SCHEDULE,
+            SCHEDULE.replace("The Green Knight", "Dune: Part Two")
+        );
+        assert_eq!(check(&mut request, now(), &body).await, (true, 1));
+        assert_eq!(request.seen_showtimes.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn error_responses_fail_the_fetch() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/schedule")
+            .with_status(500)
+            .with_body("<html><body><p>Er ging iets mis</p></body></html>")
+            .expect(1)
+            .create_async()
+            .await;
+        let fetcher = Fetcher {
+            client: Client::new(),
+            max_retries: 0,
+            delay: std::time::Duration::ZERO,
+            slow_request: std::time::Duration::ZERO,
+            check_timeout: std::time::Duration::ZERO,
+            source: ScheduleSource::Html,
+            api_url: DEFAULT_SCHEDULE_API_URL.to_string(),
+            last_request: tokio::sync::Mutex::new(None),
+            breaker: Mutex::new(CircuitBreaker::new(3, std::time::Duration::from_secs(60))),
+        };
+
+        let url = format!("{}/schedule", server.url());
+        match fetcher.fetch(&url).await {
+            Err(MonitorError::Http(e)) => {
+                assert_eq!(e.status(), Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            result => panic!("expected an HTTP error, got {:?}", result),
+        }
+        mock.assert_async().await;
+        assert_eq!(fetcher.breaker.lock().unwrap().failures, 1);
+    }
+
+    #[tokio::test]
+    async fn paginated_schedules_are_fetched_until_the_last_page() {
+        let url = "https://www.pathe.nl/cinema/schedules?cinemaId=13&date=19-08-2021";
+        let page = |title: &str, next: &str| {
+            SCHEDULE.replace("The Green Knight", title)
+                + &format!(r#"<a rel="next" href="{}">Volgende</a>"#, next)
+        };
+        let pages: HashMap<String, String> = vec![
+            (
+                url.to_string(),
+                page(
+                    "Dune",
+                    "/cinema/schedules?cinemaId=13&date=19-08-2021&page=2",
+                ),
+            ),
+            (format!("{}&page=2", url), page("The Green Knight", "#")),
+        ]
+        .into_iter()
+        .collect();
+        let fetched = AtomicUsize::new(0);
+        let fetch = |url: String| {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            let page = pages.get(&url).cloned();
+            async move { page.ok_or(MonitorError::MissingElement(url)) }
+        };
+
+        let body = fetch_schedule(url, &Selectors::default(), fetch)
+            .await
+            .unwrap();
+        assert_eq!(fetched.load(Ordering::SeqCst), 2);
+        let titles: Vec<String> = list_schedule("19-08-2021", &Selectors::default(), &body)
+            .unwrap()
+            .into_iter()
+            .map(|movie| movie.title)
+            .collect();
+        assert_eq!(titles, vec!["Dune", "The Green Knight"]);
+
+        // a page linking back to an earlier one is only fetched once
+        let looping = |url: String| {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            async move { Ok(page("Dune", &url)) }
+        };
+        fetched.store(0, Ordering::SeqCst);
+        fetch_schedule(url, &Selectors::default(), looping)
+            .await
+            .unwrap();
+        assert_eq!(fetched.load(Ordering::SeqCst), 1);
+
+        // as is a schedule that never ends
+        let endless = |url: String| {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            async move { Ok(page("Dune", &format!("{}x", url))) }
+        };
+        fetched.store(0, Ordering::SeqCst);
+        fetch_schedule(url, &Selectors::default(), endless)
+            .await
+            .unwrap();
+        assert_eq!(fetched.load(Ordering::SeqCst), MAX_SCHEDULE_PAGES);
+    }
+
+    #[test]
+    fn circuit_breaker_backs_off_and_probes() {
+        let mut breaker = CircuitBreaker::new(3, std::time::Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.remaining().is_none());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(breaker.remaining().is_some());
+
+        breaker.cooldown = std::time::Duration::ZERO;
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(breaker.remaining().is_none());
+        breaker.record_failure();
+        assert!(breaker.opened.is_some());
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        let mut never = CircuitBreaker::new(0, std::time::Duration::from_secs(60));
+        for _ in 0..10 {
+            never.record_failure();
+        }
+        assert_eq!(never.state(), BreakerState::Closed);
+    }
+}
@@ -0,0 +1,832 @@
+//! Settings read from the environment.
+
+use log::{debug, info};
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT_LANGUAGE},
+    Certificate, Client, ClientBuilder, Identity, Proxy, Url,
+};
+use std::{env, fmt::Display, fs, str::FromStr};
+
+use chrono::NaiveTime;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    Tokio1Executor,
+};
+
+use crate::api::{ScheduleSource, DEFAULT_SCHEDULE_API_URL};
+use crate::error::MonitorError;
+use crate::locale::Locale;
+use crate::notifications::{
+    render_webhook_template, Notification, TextFormat, DEFAULT_WEBHOOK_TEMPLATE, INSTANCE_NAME,
+    MESSAGE_TEMPLATE, PAD_ALIGNMENT,
+};
+use crate::request::QuietHours;
+use crate::scrape::{DEBUG_DUMP_DIR, DEFAULT_DEBUG_DUMP_DIR};
+
+pub(crate) const DEFAULT_LOG_LEVEL: &str = "Info";
+pub(crate) const DEFAULT_LOG_THROTTLE_SECONDS: u64 = 600;
+pub(crate) const DEFAULT_TIMEZONE: &str = "Europe/Amsterdam"; // based on https://docs.rs/chrono-tz/0.5.3/chrono_tz/enum.Tz.html#variants
+pub(crate) const DEFAULT_POLL_INTERVAL_MINUTES: u32 = 30;
+pub(crate) const DEFAULT_HTTP_MAX_RETRIES: u32 = 3;
+pub(crate) const DEFAULT_HTTP_TIMEOUT_SECONDS: u64 = 30;
+pub(crate) const DEFAULT_REQUEST_DELAY_MS: u64 = 500;
+pub(crate) const DEFAULT_SLOW_REQUEST_SECONDS: u64 = 30;
+pub(crate) const DEFAULT_CHECK_TIMEOUT_SECONDS: u64 = 300;
+pub(crate) const DEFAULT_MAX_CONCURRENT_CHECKS: usize = 4;
+pub(crate) const DEFAULT_CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
+pub(crate) const DEFAULT_CIRCUIT_BREAKER_COOLDOWN_MINUTES: u64 = 15;
+pub(crate) const DEFAULT_SMTP_PORT: u16 = 587;
+pub(crate) const DEFAULT_LAST_RUN_FILE: &str = "last_run.json";
+/// Pathé may serve different markup to clients that don't look like a browser.
+pub(crate) const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+pub(crate) const DEFAULT_ACCEPT_LANGUAGE: &str = "nl-NL";
+
+/// How log lines are written, set through `LOG_FORMAT`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// `[datetime][target][level] message`
+    Text,
+    /// one JSON object per line, for log aggregators
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct GenericWebhookConfig {
+    pub url: String,
+    pub template: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct EmailConfig {
+    pub host: String,
+    pub port: u16,
+    pub credentials: Option<(String, String)>,
+    pub from: Mailbox,
+    pub to: Mailbox,
+}
+
+impl EmailConfig {
+    /// Port 465 uses implicit TLS, any other port is upgraded with STARTTLS.
+    pub(crate) fn transport(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<AsyncSmtpTransport<Tokio1Executor>, lettre::transport::smtp::Error> {
+        let builder = if self.port == 465 {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?
+        };
+        let builder = builder.port(self.port).timeout(Some(timeout));
+
+        Ok(match &self.credentials {
+            Some((username, password)) => builder
+                .credentials(Credentials::new(username.clone(), password.clone()))
+                .build(),
+            None => builder.build(),
+        })
+    }
+}
+
+/// Settings read from the environment.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub log_level: log::LevelFilter,
+    pub log_format: LogFormat,
+    pub log_file: Option<String>,
+    /// per-target levels applied on top of the defaults, `LOG_TARGETS`
+    pub log_targets: Vec<(String, log::LevelFilter)>,
+    /// how long an identical warning or error isn't logged again, zero logs every repeat
+    pub log_throttle: std::time::Duration,
+    pub timezone: chrono_tz::Tz,
+    pub poll_interval_minutes: u32,
+    /// daily times to check at instead of every poll interval, `CHECK_TIMES`
+    pub check_times: Vec<NaiveTime>,
+    /// the longest random delay before a scheduled check, spreads the load on Pathé
+    pub poll_jitter: std::time::Duration,
+    pub http_timeout: std::time::Duration,
+    pub http_max_retries: u32,
+    /// sent with every request, `USER_AGENT`
+    pub user_agent: String,
+    /// sent with every request unless empty, `ACCEPT_LANGUAGE`
+    pub accept_language: Option<String>,
+    /// proxy for all requests, `PROXY_URL` or `ALL_PROXY`, instead of `HTTP_PROXY` and `HTTPS_PROXY`
+    pub proxy_url: Option<String>,
+    /// PEM file with CA certificates to trust next to the built-in ones, e.g. of a TLS-intercepting proxy
+    pub ca_cert_path: Option<String>,
+    /// PEM file with the client certificate for mutual TLS, and its private key unless `client_key_path` is set
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+    /// minimum time between the start of two requests to Pathé
+    pub request_delay: std::time::Duration,
+    /// a request taking longer to check is warned about, zero never warns
+    pub slow_request: std::time::Duration,
+    /// checking a request that takes longer is abandoned, so the other requests are still checked, zero never abandons
+    pub check_timeout: std::time::Duration,
+    /// whether the schedules are scraped, read as JSON, or read as JSON with scraping as the fallback
+    pub schedule_source: ScheduleSource,
+    /// the JSON schedules, with `{cinema}` and `{date}` placeholders
+    pub schedule_api_url: String,
+    /// how many requests are checked at the same time
+    pub max_concurrent_checks: usize,
+    /// consecutive failing Pathé requests before backing off, 0 never backs off
+    pub circuit_breaker_threshold: u32,
+    /// how long to back off before probing Pathé again
+    pub circuit_breaker_cooldown: std::time::Duration,
+    pub discord_webhook_url: Option<String>,
+    /// Discord webhook that is told about failing checks
+    pub error_webhook_url: Option<String>,
+    pub telegram: Option<TelegramConfig>,
+    pub generic_webhook: Option<GenericWebhookConfig>,
+    pub email: Option<EmailConfig>,
+    pub health_port: Option<u16>,
+    pub metrics_port: Option<u16>,
+    pub dry_run: bool,
+    /// when notifications are held until the window ends, `QUIET_HOURS`
+    pub quiet_hours: Option<QuietHours>,
+    /// drop the notifications found during quiet hours instead of holding them, `QUIET_HOURS_MODE=drop`
+    pub drop_in_quiet_hours: bool,
+    /// where the outcome of every check is written, `LAST_RUN_FILE`
+    pub last_run_file: Option<String>,
+    /// send a summary of the watched requests when starting
+    pub notify_on_startup: bool,
+    /// forget which showtimes have been notified when starting
+    pub reset_state: bool,
+    /// the first line of every notification, `MESSAGE_TEMPLATE`, unless a request has its own
+    pub message_template: Option<String>,
+    /// the name of this monitor in notifications, `MONITOR_NAME` or else the `HOSTNAME`
+    pub instance_name: Option<String>,
+    /// pad Discord embeds to full rows of showtimes, `DISCORD_PAD_ALIGNMENT`
+    pub discord_pad_alignment: bool,
+    /// where schedules that couldn't be scraped are written to when `DEBUG_DUMP` is on, `DEBUG_DUMP_DIR`
+    pub debug_dump_dir: Option<String>,
+    /// settings that were invalid, but have a sensible default to fall back to
+    pub warnings: Vec<String>,
+}
+
+/// Reads the SMTP settings, which are only required once `SMTP_HOST` or `EMAIL_TO` is set.
+pub(crate) fn email_config_from_env(
+    errors: &mut Vec<String>,
+    warnings: &mut Vec<String>,
+) -> Option<EmailConfig> {
+    let (host, to) = match (env::var("SMTP_HOST"), env::var("EMAIL_TO")) {
+        (Ok(host), Ok(to)) => (host, to),
+        (Ok(_), Err(_)) => {
+            errors.push("`EMAIL_TO` is missing, it is required with `SMTP_HOST`".to_string());
+            return None;
+        }
+        (Err(_), Ok(_)) => {
+            errors.push("`SMTP_HOST` is missing, it is required with `EMAIL_TO`".to_string());
+            return None;
+        }
+        (Err(_), Err(_)) => return None,
+    };
+
+    let mut mailbox = |name: &str, address: String| {
+        address
+            .parse::<Mailbox>()
+            .map_err(|e| errors.push(format!("`{}` '{}' is invalid: {}", name, address, e)))
+            .ok()
+    };
+    let to = mailbox("EMAIL_TO", to);
+    let from = match env::var("EMAIL_FROM") {
+        Ok(from) => mailbox("EMAIL_FROM", from),
+        Err(_) => to.clone(),
+    };
+
+    let credentials = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+        (Ok(username), Ok(password)) => Some((username, password)),
+        (Err(_), Err(_)) => None,
+        _ => {
+            errors.push("`SMTP_USERNAME` and `SMTP_PASSWORD` must be set together".to_string());
+            None
+        }
+    };
+
+    let port = env_var_or("SMTP_PORT", DEFAULT_SMTP_PORT, |port| *port > 0, warnings);
+
+    Some(EmailConfig {
+        host,
+        port,
+        credentials,
+        from: from?,
+        to: to?,
+    })
+}
+
+/// Parses an optional environment variable, remembering a warning and falling back to `default` on invalid input.
+pub(crate) fn env_var_or<T: FromStr + Display>(
+    name: &str,
+    default: T,
+    valid: impl Fn(&T) -> bool,
+    warnings: &mut Vec<String>,
+) -> T {
+    match env::var(name) {
+        Err(_) => default,
+        Ok(value) => match value.parse::<T>() {
+            Ok(parsed) if valid(&parsed) => parsed,
+            _ => {
+                warnings.push(format!(
+                    "invalid `{}` '{}', falling back to {}",
+                    name, value, default
+                ));
+                default
+            }
+        },
+    }
+}
+
+/// A switch like `DRY_RUN`, on with `1`, `true`, `yes` or `on` and off with `0`, `false`, `no`, `off` or nothing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Flag(pub(crate) bool);
+
+impl FromStr for Flag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Flag(true)),
+            "" | "0" | "false" | "no" | "off" => Ok(Flag(false)),
+            _ => Err(format!("'{}' is neither on nor off", s)),
+        }
+    }
+}
+
+impl Display for Flag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.0 { "on" } else { "off" })
+    }
+}
+
+/// Whether the switch in the `name` environment variable is on, off when it isn't set.
+pub(crate) fn env_flag(name: &str, warnings: &mut Vec<String>) -> bool {
+    env_var_or(name, Flag(false), |_| true, warnings).0
+}
+
+/// Parses `target=level` pairs separated by commas, e.g. `reqwest=debug,scraper=off`.
+pub(crate) fn parse_log_targets(targets: &str) -> Result<Vec<(String, log::LevelFilter)>, String> {
+    targets
+        .split(',')
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+        .map(|target| match target.split_once('=') {
+            Some((name, level)) if !name.trim().is_empty() => level
+                .trim()
+                .parse()
+                .map(|level| (name.trim().to_string(), level))
+                .map_err(|_| format!("'{}' has an invalid level", target)),
+            _ => Err(format!("'{}' is not a `target=level` pair", target)),
+        })
+        .collect()
+}
+
+/// The URL without its credentials, to log.
+pub(crate) fn redact_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) => {
+            if !url.username().is_empty() {
+                let _ = url.set_username("***");
+            }
+            if url.password().is_some() {
+                let _ = url.set_password(Some("***"));
+            }
+            url.to_string()
+        }
+        Err(_) => "<invalid URL>".to_string(),
+    }
+}
+
+/// Reads all environment variables at once, so every problem is reported together.
+pub fn validate_env() -> Result<Config, Vec<String>> {
+    let mut errors = vec![];
+    let mut warnings = vec![];
+
+    let log_level = env::var("LOG_LEVEL")
+        .unwrap_or(DEFAULT_LOG_LEVEL.to_string())
+        .parse()
+        .map_err(|_| {
+            errors.push(
+                "`LOG_LEVEL` must be one of Off, Error, Warn, Info, Debug or Trace".to_string(),
+            )
+        })
+        .unwrap_or(log::LevelFilter::Info);
+
+    let log_format = env::var("LOG_FORMAT")
+        .unwrap_or_else(|_| "text".to_string())
+        .parse()
+        .map_err(|_| errors.push("`LOG_FORMAT` must be either text or json".to_string()))
+        .unwrap_or(LogFormat::Text);
+
+    let log_targets = parse_log_targets(&env::var("LOG_TARGETS").unwrap_or_default())
+        .map_err(|e| errors.push(format!("`LOG_TARGETS` is invalid: {}", e)))
+        .unwrap_or_default();
+
+    if env_flag("STRICT_TZ", &mut warnings) && env::var_os("TIMEZONE").is_none() {
+        errors.push("`TIMEZONE` is required with `STRICT_TZ`".to_string());
+    }
+    let timezone = env::var("TIMEZONE")
+        .unwrap_or(DEFAULT_TIMEZONE.to_string())
+        .parse()
+        .map_err(|e| errors.push(format!("`TIMEZONE` is invalid: {}", e)))
+        .unwrap_or(chrono_tz::Europe::Amsterdam);
+
+    let log_throttle = std::time::Duration::from_secs(env_var_or(
+        "LOG_THROTTLE_SECONDS",
+        DEFAULT_LOG_THROTTLE_SECONDS,
+        |_| true,
+        &mut warnings,
+    ));
+
+    let poll_interval_minutes = env_var_or(
+        "POLL_INTERVAL_MINUTES",
+        DEFAULT_POLL_INTERVAL_MINUTES,
+        |minutes| *minutes > 0,
+        &mut warnings,
+    );
+    let mut check_times = vec![];
+    for time in env::var("CHECK_TIMES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|time| !time.is_empty())
+    {
+        match NaiveTime::parse_from_str(time, "%H:%M") {
+            Ok(time) => check_times.push(time),
+            Err(_) => errors.push(format!(
+                "`CHECK_TIMES` has an invalid time `{}`, use HH:MM",
+                time
+            )),
+        }
+    }
+    check_times.sort();
+    check_times.dedup();
+    let poll_jitter = std::time::Duration::from_secs(env_var_or(
+        "POLL_JITTER_SECONDS",
+        0,
+        |seconds| *seconds < 60 * u64::from(poll_interval_minutes),
+        &mut warnings,
+    ));
+    let http_timeout = std::time::Duration::from_secs(env_var_or(
+        "HTTP_TIMEOUT_SECONDS",
+        DEFAULT_HTTP_TIMEOUT_SECONDS,
+        |seconds| *seconds > 0,
+        &mut warnings,
+    ));
+    let http_max_retries = env_var_or(
+        "HTTP_MAX_RETRIES",
+        DEFAULT_HTTP_MAX_RETRIES,
+        |_| true,
+        &mut warnings,
+    );
+    let proxy_url = ["PROXY_URL", "ALL_PROXY", "all_proxy"]
+        .iter()
+        .find_map(|name| {
+            env::var(name)
+                .ok()
+                .filter(|url| !url.is_empty())
+                .map(|url| (name, url))
+        })
+        .and_then(|(name, url)| match Url::parse(&url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https" | "socks5" | "socks5h") => {
+                Some(url)
+            }
+            Ok(parsed) => {
+                errors.push(format!(
+                    "`{}` uses an unsupported scheme `{}`, use an HTTP(S) or SOCKS5 proxy",
+                    name,
+                    parsed.scheme()
+                ));
+                None
+            }
+            Err(e) => {
+                errors.push(format!("`{}` is invalid: {}", name, e));
+                None
+            }
+        });
+    let path = |name: &str| env::var(name).ok().filter(|path| !path.is_empty());
+    let ca_cert_path = path("CA_CERT_PATH");
+    let client_cert_path = path("CLIENT_CERT_PATH");
+    let client_key_path = path("CLIENT_KEY_PATH");
+    // loading them now tells what is wrong with the files, instead of failing the first request
+    if let Err(e) = add_certificates(
+        Client::builder(),
+        ca_cert_path.as_deref(),
+        client_cert_path.as_deref(),
+        client_key_path.as_deref(),
+    )
+    .and_then(|builder| {
+        builder
+            .build()
+            .map_err(|e| format!("the TLS certificates can't be used: {}", e))
+    }) {
+        errors.push(e);
+    }
+
+    let request_delay = std::time::Duration::from_millis(env_var_or(
+        "REQUEST_DELAY_MS",
+        DEFAULT_REQUEST_DELAY_MS,
+        |_| true,
+        &mut warnings,
+    ));
+    let slow_request = std::time::Duration::from_secs(env_var_or(
+        "SLOW_REQUEST_SECONDS",
+        DEFAULT_SLOW_REQUEST_SECONDS,
+        |_| true,
+        &mut warnings,
+    ));
+    let check_timeout = std::time::Duration::from_secs(env_var_or(
+        "CHECK_TIMEOUT_SECONDS",
+        DEFAULT_CHECK_TIMEOUT_SECONDS,
+        |_| true,
+        &mut warnings,
+    ));
+    let schedule_source = match env::var("SCHEDULE_SOURCE") {
+        Ok(source) if !source.is_empty() => source.parse().unwrap_or_else(|e| {
+            errors.push(format!("`SCHEDULE_SOURCE` is invalid: {}", e));
+            ScheduleSource::default()
+        }),
+        _ => ScheduleSource::default(),
+    };
+    let schedule_api_url = env::var("SCHEDULE_API_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| DEFAULT_SCHEDULE_API_URL.to_string());
+    if !(schedule_api_url.contains("{cinema}") && schedule_api_url.contains("{date}")) {
+        errors.push(format!(
+            "`SCHEDULE_API_URL` '{}' needs a {{cinema}} and a {{date}} placeholder",
+            schedule_api_url
+        ));
+    }
+    let max_concurrent_checks = env_var_or(
+        "MAX_CONCURRENT_CHECKS",
+        DEFAULT_MAX_CONCURRENT_CHECKS,
+        |checks| *checks > 0,
+        &mut warnings,
+    );
+    let circuit_breaker_threshold = env_var_or(
+        "CIRCUIT_BREAKER_THRESHOLD",
+        DEFAULT_CIRCUIT_BREAKER_THRESHOLD,
+        |_| true,
+        &mut warnings,
+    );
+    let circuit_breaker_cooldown = std::time::Duration::from_secs(
+        60 * env_var_or(
+            "CIRCUIT_BREAKER_COOLDOWN_MINUTES",
+            DEFAULT_CIRCUIT_BREAKER_COOLDOWN_MINUTES,
+            |minutes| *minutes > 0,
+            &mut warnings,
+        ),
+    );
+
+    let mut header = |name: &str, default: &str| {
+        let value = env::var(name).unwrap_or_else(|_| default.to_string());
+        match HeaderValue::from_str(&value) {
+            Ok(_) => Some(value),
+            Err(_) => {
+                errors.push(format!(
+                    "`{}` '{}' is not a valid header value",
+                    name, value
+                ));
+                None
+            }
+        }
+    };
+    let user_agent = header("USER_AGENT", DEFAULT_USER_AGENT).unwrap_or_default();
+    let accept_language =
+        header("ACCEPT_LANGUAGE", DEFAULT_ACCEPT_LANGUAGE).filter(|language| !language.is_empty());
+
+    let mut port = |name: &str| {
+        env::var(name).ok().and_then(|port| {
+            port.parse()
+                .map_err(|_| errors.push(format!("`{}` '{}' is not a valid port", name, port)))
+                .ok()
+        })
+    };
+    let health_port = port("HEALTH_PORT");
+    let metrics_port = port("METRICS_PORT");
+
+    let discord_webhook_url = env::var("DISCORD_WEBHOOK_URL").ok();
+
+    let telegram = match (env::var("TELEGRAM_BOT_TOKEN"), env::var("TELEGRAM_CHAT_ID")) {
+        (Ok(bot_token), Ok(chat_id)) => Some(TelegramConfig { bot_token, chat_id }),
+        (Ok(_), Err(_)) => {
+            errors.push(
+                "`TELEGRAM_CHAT_ID` is missing, it is required with `TELEGRAM_BOT_TOKEN`"
+                    .to_string(),
+            );
+            None
+        }
+        (Err(_), Ok(_)) => {
+            errors.push(
+                "`TELEGRAM_BOT_TOKEN` is missing, it is required with `TELEGRAM_CHAT_ID`"
+                    .to_string(),
+            );
+            None
+        }
+        (Err(_), Err(_)) => None,
+    };
+
+    let generic_webhook = match (env::var("WEBHOOK_URL"), env::var("WEBHOOK_TEMPLATE")) {
+        (Ok(url), template) => {
+            let template = template.unwrap_or_else(|_| DEFAULT_WEBHOOK_TEMPLATE.to_string());
+            let example = Notification {
+                movie: "movie".to_string(),
+                title: "title".to_string(),
+                date: "date".to_string(),
+                cinema: "cinema".to_string(),
+                url: "url".to_string(),
+                thumbnail: "thumbnail".to_string(),
+                showtimes: vec![],
+                request_id: "id".to_string(),
+                message_template: None,
+            };
+
+            match render_webhook_template(&template, &example, TextFormat::Plain) {
+                Ok(_) => Some(GenericWebhookConfig { url, template }),
+                Err(e) => {
+                    errors.push(format!("`WEBHOOK_TEMPLATE` is not valid JSON: {}", e));
+                    None
+                }
+            }
+        }
+        (Err(_), Ok(_)) => {
+            errors.push(
+                "`WEBHOOK_URL` is missing, it is required with `WEBHOOK_TEMPLATE`".to_string(),
+            );
+            None
+        }
+        (Err(_), Err(_)) => None,
+    };
+
+    let email = email_config_from_env(&mut errors, &mut warnings);
+
+    if let Ok(locale) = env::var("LOCALE") {
+        if let Err(e) = locale.parse::<Locale>() {
+            errors.push(format!("`LOCALE` is invalid: {}", e));
+        }
+    }
+
+    let quiet_hours = env::var("QUIET_HOURS")
+        .ok()
+        .filter(|quiet_hours| !quiet_hours.is_empty())
+        .and_then(|quiet_hours| {
+            quiet_hours
+                .parse()
+                .map_err(|e| errors.push(format!("`QUIET_HOURS` is invalid: {}", e)))
+                .ok()
+        });
+    let drop_in_quiet_hours = match env::var("QUIET_HOURS_MODE")
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "" | "hold" => false,
+        "drop" => true,
+        _ => {
+            errors.push("`QUIET_HOURS_MODE` must be either hold or drop".to_string());
+            false
+        }
+    };
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let dry_run = env_flag("DRY_RUN", &mut warnings);
+    let notify_on_startup = env_flag("NOTIFY_ON_STARTUP", &mut warnings);
+    let reset_state = env_flag("RESET_STATE", &mut warnings);
+    let message_template = env::var("MESSAGE_TEMPLATE")
+        .ok()
+        .filter(|template| !template.trim().is_empty());
+    let instance_name = env::var("MONITOR_NAME")
+        .or_else(|_| env::var("HOSTNAME"))
+        .ok()
+        .filter(|name| !name.is_empty());
+    let discord_pad_alignment =
+        env_var_or("DISCORD_PAD_ALIGNMENT", Flag(true), |_| true, &mut warnings).0;
+    let debug_dump_dir = env_flag("DEBUG_DUMP", &mut warnings).then(|| {
+        env::var("DEBUG_DUMP_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| DEFAULT_DEBUG_DUMP_DIR.to_string())
+    });
+
+    Ok(Config {
+        log_level,
+        log_format,
+        log_file: env::var("LOG_FILE").ok().filter(|path| !path.is_empty()),
+        log_targets,
+        log_throttle,
+        timezone,
+        poll_interval_minutes,
+        check_times,
+        poll_jitter,
+        http_timeout,
+        http_max_retries,
+        user_agent,
+        accept_language,
+        proxy_url,
+        ca_cert_path,
+        client_cert_path,
+        client_key_path,
+        request_delay,
+        slow_request,
+        check_timeout,
+        schedule_source,
+        schedule_api_url,
+        max_concurrent_checks,
+        circuit_breaker_threshold,
+        circuit_breaker_cooldown,
+        discord_webhook_url,
+        error_webhook_url: env::var("ERROR_WEBHOOK_URL").ok(),
+        telegram,
+        generic_webhook,
+        email,
+        health_port,
+        metrics_port,
+        dry_run,
+        quiet_hours,
+        drop_in_quiet_hours,
+        last_run_file: match env::var("LAST_RUN_FILE") {
+            Ok(path) => Some(path).filter(|path| !path.is_empty()),
+            Err(_) => Some(DEFAULT_LAST_RUN_FILE.to_string()),
+        },
+        notify_on_startup,
+        reset_state,
+        message_template,
+        instance_name,
+        discord_pad_alignment,
+        debug_dump_dir,
+        warnings,
+    })
+}
+
+/// Hands the settings that are read while notifying and scraping to those modules, once at startup.
+pub fn share_settings(config: &Config) {
+    let _ = MESSAGE_TEMPLATE.set(config.message_template.clone());
+    let _ = INSTANCE_NAME.set(config.instance_name.clone());
+    let _ = PAD_ALIGNMENT.set(config.discord_pad_alignment);
+    let _ = DEBUG_DUMP_DIR.set(config.debug_dump_dir.clone());
+}
+
+/// Reads a PEM file from the path in the `name` environment variable.
+pub(crate) fn read_pem(name: &str, path: &str) -> Result<Vec<u8>, String> {
+    let pem = fs::read(path).map_err(|e| format!("`{}` `{}` can't be read: {}", name, path, e))?;
+    if !String::from_utf8_lossy(&pem).contains("-----BEGIN ") {
+        return Err(format!("`{}` `{}` is not a PEM file", name, path));
+    }
+
+    Ok(pem)
+}
+
+/// Trusts the CA certificates and authenticates with the client certificate, when their files are set.
+pub(crate) fn add_certificates(
+    mut builder: ClientBuilder,
+    ca_cert_path: Option<&str>,
+    client_cert_path: Option<&str>,
+    client_key_path: Option<&str>,
+) -> Result<ClientBuilder, String> {
+    if let Some(path) = ca_cert_path {
+        let certificate = Certificate::from_pem(&read_pem("CA_CERT_PATH", path)?)
+            .map_err(|e| format!("`CA_CERT_PATH` `{}` is invalid: {}", path, e))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+
+    match (client_cert_path, client_key_path) {
+        (Some(path), key_path) => {
+            let mut pem = read_pem("CLIENT_CERT_PATH", path)?;
+            if let Some(key_path) = key_path {
+                pem.push(b'\n');
+                pem.extend(read_pem("CLIENT_KEY_PATH", key_path)?);
+            }
+            let identity = Identity::from_pem(&pem).map_err(|e| {
+                format!(
+                    "`CLIENT_CERT_PATH` `{}` needs a certificate and its private key: {}",
+                    path, e
+                )
+            })?;
+            builder = builder.identity(identity);
+        }
+        (None, Some(_)) => {
+            return Err(
+                "`CLIENT_CERT_PATH` is missing, it is required with `CLIENT_KEY_PATH`".to_string(),
+            )
+        }
+        (None, None) => {}
+    }
+
+    Ok(builder)
+}
+
+/// Builds the HTTP client shared by all Pathé and notification calls.
+pub(crate) fn setup_client(config: &Config) -> Result<Client, MonitorError> {
+    debug!(
+        "initializing HTTP client with a {:?} timeout",
+        config.http_timeout
+    );
+
+    debug!("identifying as '{}'", config.user_agent);
+
+    let mut headers = HeaderMap::new();
+    // validated by `validate_env`
+    if let Some(Ok(language)) = config.accept_language.as_deref().map(HeaderValue::from_str) {
+        headers.insert(ACCEPT_LANGUAGE, language);
+    }
+
+    let mut builder = Client::builder()
+        .timeout(config.http_timeout)
+        .user_agent(config.user_agent.as_str())
+        .default_headers(headers);
+
+    if let Some(path) = &config.ca_cert_path {
+        info!("Trusting the CA certificates in `{}`", path);
+    }
+    if let Some(path) = &config.client_cert_path {
+        info!("Authenticating with the client certificate in `{}`", path);
+    }
+    builder = add_certificates(
+        builder,
+        config.ca_cert_path.as_deref(),
+        config.client_cert_path.as_deref(),
+        config.client_key_path.as_deref(),
+    )
+    .map_err(MonitorError::Certificate)?;
+
+    match &config.proxy_url {
+        Some(proxy_url) => {
+            info!(
+                "Sending all requests through proxy {}",
+                redact_url(proxy_url)
+            );
+            builder = builder.proxy(Proxy::all(proxy_url.as_str())?);
+        }
+        None => {
+            // picked up by reqwest itself
+            for name in ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"] {
+                if let Some(proxy_url) = env::var(name).ok().filter(|url| !url.is_empty()) {
+                    info!("Using proxy {} from `{}`", redact_url(&proxy_url), name);
+                }
+            }
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switches_can_be_turned_off() {
+        for on in ["1", "true", "Yes", "on"] {
+            assert_eq!(on.parse(), Ok(Flag(true)));
+        }
+        for off in ["", "0", "false", "NO", "off"] {
+            assert_eq!(off.parse(), Ok(Flag(false)));
+        }
+        assert!("maybe".parse::<Flag>().is_err());
+    }
+
+    #[test]
+    fn unusable_certificate_files_are_explained() {
+        let path = std::env::temp_dir().join(format!("ca-{}.pem", std::process::id()));
+        let path = path.to_str().unwrap();
+        let certificates = |ca: Option<&str>, cert: Option<&str>, key: Option<&str>| {
+            add_certificates(Client::builder(), ca, cert, key).map(|_| ())
+        };
+
+        assert!(certificates(None, None, None).is_ok());
+        assert!(certificates(Some(path), None, None)
+            .unwrap_err()
+            .contains("can't be read"));
+
+        fs::write(path, "not a certificate").unwrap();
+        let error = certificates(Some(path), None, None).unwrap_err();
+        fs::remove_file(path).unwrap();
+        assert!(error.contains("is not a PEM file"));
+
+        assert!(certificates(None, None, Some(path))
+            .unwrap_err()
+            .contains("`CLIENT_CERT_PATH` is missing"));
+    }
+}
@@ -0,0 +1,256 @@
+//! The health, status and metrics endpoints.
+
+use log::{debug, error, info, trace, warn};
+
+use serde_json::json;
+use std::{
+    fs,
+    sync::{Arc, LazyLock, Mutex},
+    thread,
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use prometheus::{
+    register_gauge, register_histogram, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Encoder, Gauge, Histogram, IntCounter, IntCounterVec, IntGauge,
+    TextEncoder,
+};
+use serde::Serialize;
+
+use crate::error::MonitorError;
+use crate::request::MovieMonitorRequest;
+use crate::scrape::BreakerState;
+
+pub(crate) const HEALTH_MAX_HEARTBEAT_AGE: std::time::Duration =
+    std::time::Duration::from_secs(10 * 60);
+
+/// What the monitor has been up to, exposed on `/status`.
+#[derive(Serialize, Default, Debug)]
+pub struct MonitorStatus {
+    #[serde(skip)]
+    pub heartbeat: Option<Instant>,
+    pub requests: usize,
+    pub last_check: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub circuit_breaker: BreakerState,
+}
+
+impl MonitorStatus {
+    pub fn heartbeat(&mut self) {
+        self.heartbeat = Some(Instant::now());
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        self.heartbeat
+            .map(|heartbeat| heartbeat.elapsed() < HEALTH_MAX_HEARTBEAT_AGE)
+            .unwrap_or(false)
+    }
+}
+
+/// How checking a request went.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Matched,
+    NotMatched,
+    Errored,
+    /// disabled, past, or not checked while Pathé keeps failing
+    Skipped,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RequestRun {
+    pub id: String,
+    pub request: String,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RequestRun {
+    pub(crate) fn new(
+        request: &MovieMonitorRequest,
+        outcome: Outcome,
+        error: Option<String>,
+    ) -> Self {
+        RequestRun {
+            id: request.id(),
+            request: request.to_string(),
+            outcome,
+            error,
+        }
+    }
+}
+
+/// The outcome of the last check, written to `LAST_RUN_FILE` for deployments that can't reach `/status`.
+#[derive(Serialize, Debug)]
+pub struct LastRun {
+    pub timestamp: DateTime<Utc>,
+    pub requests: Vec<RequestRun>,
+    pub errors: Vec<String>,
+}
+
+impl LastRun {
+    pub(crate) fn write(&self, path: &str) {
+        debug!("writing the outcome of the check to `{}`", path);
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!(
+                "failed writing the outcome of the check to `{}`: {}",
+                path, e
+            );
+        }
+    }
+}
+
+pub(crate) static SCRAPES_TOTAL: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("pathe_scrapes_total", "Number of Pathé schedules fetched").unwrap()
+});
+pub(crate) static SCRAPE_ERRORS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "pathe_scrape_errors_total",
+        "Number of Pathé schedules that failed to fetch or parse",
+        &["cinema"]
+    )
+    .unwrap()
+});
+pub(crate) static NOTIFICATIONS_SENT_TOTAL: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "pathe_notifications_sent_total",
+        "Number of notifications delivered by a notifier"
+    )
+    .unwrap()
+});
+pub(crate) static REQUEST_DURATION_SECONDS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "pathe_request_duration_seconds",
+        "Duration of fetching and checking a Pathé schedule"
+    )
+    .unwrap()
+});
+pub(crate) static LAST_SUCCESS_TIMESTAMP_SECONDS: LazyLock<Gauge> = LazyLock::new(|| {
+    register_gauge!(
+        "pathe_last_success_timestamp_seconds",
+        "Unix time of the last Pathé schedule that was fetched and checked"
+    )
+    .unwrap()
+});
+pub(crate) static UP: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "pathe_up",
+        "Whether the last check went without errors, 0 until the first check"
+    )
+    .unwrap()
+});
+
+/// Registers every metric up front, so they're exported before the first check.
+pub(crate) fn register_metrics() {
+    LazyLock::force(&SCRAPES_TOTAL);
+    LazyLock::force(&SCRAPE_ERRORS_TOTAL);
+    LazyLock::force(&NOTIFICATIONS_SENT_TOTAL);
+    LazyLock::force(&REQUEST_DURATION_SECONDS);
+    LazyLock::force(&LAST_SUCCESS_TIMESTAMP_SECONDS);
+    LazyLock::force(&UP);
+}
+
+pub(crate) fn render_metrics() -> String {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap_or_else(|e| error!("failed encoding metrics: {}", e));
+
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+pub(crate) fn header(header: &str) -> tiny_http::Header {
+    header.parse().unwrap()
+}
+
+/// Serves `/health` and `/status` (given a status) and/or `/metrics` on its own thread,
+/// so it can't interfere with the scheduler.
+pub fn setup_http_server(
+    port: u16,
+    status: Option<Arc<Mutex<MonitorStatus>>>,
+    metrics: bool,
+) -> Result<(), MonitorError> {
+    let server = tiny_http::Server::http(("0.0.0.0", port))
+        .map_err(|e| MonitorError::Server(e.to_string()))?;
+    info!(
+        "Serving{}{} on port {}",
+        if status.is_some() {
+            " `/health` `/status`"
+        } else {
+            ""
+        },
+        if metrics { " `/metrics`" } else { "" },
+        port
+    );
+
+    if metrics {
+        register_metrics();
+    }
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            trace!("http server: {} {}", request.method(), request.url());
+            let response = match (request.url(), &status) {
+                ("/health", Some(status)) => {
+                    if status.lock().unwrap().is_alive() {
+                        tiny_http::Response::from_string("OK")
+                    } else {
+                        tiny_http::Response::from_string("scheduler stalled").with_status_code(503)
+                    }
+                }
+                ("/status", Some(status)) => {
+                    tiny_http::Response::from_string(json!(*status.lock().unwrap()).to_string())
+                        .with_header(header("Content-Type: application/json"))
+                }
+                ("/metrics", _) if metrics => tiny_http::Response::from_string(render_metrics())
+                    .with_header(header("Content-Type: text/plain; version=0.0.4")),
+                _ => tiny_http::Response::from_string("not found").with_status_code(404),
+            };
+
+            if let Err(e) = request.respond(response) {
+                warn!("http server failed responding: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::request;
+
+    #[test]
+    fn last_run_is_written_as_json() {
+        let path = std::env::temp_dir().join(format!("last_run-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let request = request();
+
+        LastRun {
+            timestamp: Utc::now(),
+            requests: vec![
+                RequestRun::new(&request, Outcome::NotMatched, None),
+                RequestRun::new(&request, Outcome::Errored, Some("kapot".to_string())),
+            ],
+            errors: vec!["kapot".to_string()],
+        }
+        .write(path);
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(written["requests"][0]["id"], request.id());
+        assert_eq!(written["requests"][0]["outcome"], "not_matched");
+        assert!(written["requests"][0].get("error").is_none());
+        assert_eq!(written["requests"][1]["error"], "kapot");
+        assert_eq!(written["errors"], json!(["kapot"]));
+    }
+}
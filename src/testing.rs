@@ -0,0 +1,170 @@
+//! Helpers shared by the tests of the different modules.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Offset, Utc};
+
+use crate::cinema::{Cinema, CinemaRef, CinemaSpec};
+use crate::error::MonitorError;
+use crate::notifications::{Notification, Notifier};
+use crate::request::{DateSpec, MatchMode, MovieMonitorRequest, MovieSpec};
+use crate::scrape::{check_response, Selectors};
+
+pub(crate) const SPUIMARKT: CinemaRef = CinemaRef::Known(Cinema::Spuimarkt);
+
+pub(crate) const SCHEDULE: &str = r#"
+    <div class="schedule-simple__item">
+        <div class="schedule-simple__poster"><img src="https://pathe.nl/poster.jpg"></div>
+        <h4><a href="/film/12345/the-green-knight">The Green Knight</a></h4>
+        <a class="schedule-time" data-href="/tickets/1">
+            <span class="schedule-time__start">19:00</span>
+            <span class="schedule-time__end">21:10</span>
+            <span class="schedule-time__label">OV</span>
+        </a>
+        <a class="schedule-time" data-href="/tickets/2">
+            <span class="schedule-time__start">14:30</span>
+            <span class="schedule-time__end">16:40</span>
+            <span class="schedule-time__label">NL</span>
+        </a>
+    </div>
+"#;
+
+/// Remembers the notifications it was asked to send.
+#[derive(Default)]
+pub(crate) struct CapturingNotifier(pub(crate) Mutex<Vec<Notification>>);
+
+#[async_trait]
+impl Notifier for CapturingNotifier {
+    fn name(&self) -> &'static str {
+        "Capturing"
+    }
+
+    async fn send(&self, msgs: &[Notification]) -> Result<(), MonitorError> {
+        self.0.lock().unwrap().extend_from_slice(msgs);
+        Ok(())
+    }
+
+    fn preview(&self, msgs: &[Notification]) -> String {
+        format!("{} notification(s)", msgs.len())
+    }
+}
+
+pub(crate) struct CountingNotifier(pub(crate) Arc<AtomicUsize>);
+
+#[async_trait]
+impl Notifier for CountingNotifier {
+    fn name(&self) -> &'static str {
+        "Counting"
+    }
+
+    async fn send(&self, _msgs: &[Notification]) -> Result<(), MonitorError> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn send_text(&self, _text: &str) -> Result<(), MonitorError> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn preview(&self, msgs: &[Notification]) -> String {
+        format!("{} notification(s)", msgs.len())
+    }
+}
+
+pub(crate) fn request() -> MovieMonitorRequest {
+    MovieMonitorRequest {
+        cinema: CinemaSpec::Single(CinemaRef::Known(Cinema::Spuimarkt)),
+        date: DateSpec::Single("19-08-2021".to_string()),
+        movie: MovieSpec::Single("The Green Knight".to_string()),
+        movie_slug: None,
+        match_mode: MatchMode::default(),
+        normalize: true,
+        fuzzy_threshold: None,
+        versions: None,
+        experiences: None,
+        halls: None,
+        include_previews: true,
+        require_bookable: false,
+        webhook_url: None,
+        on_match_command: None,
+        priority: 0,
+        earliest: None,
+        latest: None,
+        notify_on_removal: false,
+        message_template: None,
+        quiet_hours: None,
+        timezone: None,
+        enabled: true,
+        oneshot: false,
+        cooldown_minutes: None,
+        last_notified: None,
+        seen_showtimes: vec![],
+        aliases: HashMap::new(),
+    }
+}
+
+/// The current time, in the offset `check_response` takes.
+pub(crate) fn now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&Utc.fix())
+}
+
+/// Runs `body` through `check_response` as the schedule of `request` on 19-08-2021, returns whether it matched and how
+/// many messages were sent.
+pub(crate) async fn check(
+    request: &mut MovieMonitorRequest,
+    now: DateTime<FixedOffset>,
+    body: &str,
+) -> (bool, usize) {
+    run_check(request, &Selectors::default(), false, now, body).await
+}
+
+/// Like `check`, but with other selectors.
+pub(crate) async fn check_with_selectors(
+    request: &mut MovieMonitorRequest,
+    selectors: &Selectors,
+    now: DateTime<FixedOffset>,
+    body: &str,
+) -> (bool, usize) {
+    run_check(request, selectors, false, now, body).await
+}
+
+/// Like `check`, but on a dry run.
+pub(crate) async fn dry_check(
+    request: &mut MovieMonitorRequest,
+    now: DateTime<FixedOffset>,
+    body: &str,
+) -> (bool, usize) {
+    run_check(request, &Selectors::default(), true, now, body).await
+}
+
+async fn run_check(
+    request: &mut MovieMonitorRequest,
+    selectors: &Selectors,
+    dry_run: bool,
+    now: DateTime<FixedOffset>,
+    body: &str,
+) -> (bool, usize) {
+    let sent = Arc::new(AtomicUsize::new(0));
+    let notifiers: Vec<Arc<dyn Notifier>> = vec![Arc::new(CountingNotifier(sent.clone()))];
+    let found = check_response(
+        request,
+        "19-08-2021",
+        selectors,
+        &notifiers,
+        dry_run,
+        now,
+        body,
+    )
+    .await
+    .unwrap();
+
+    (found, sent.load(Ordering::SeqCst))
+}
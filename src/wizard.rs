@@ -0,0 +1,172 @@
+//! Asks for the requests to add to the config file, for `pathe-monitor init`.
+
+use serde_json::json;
+use std::io::{self, BufRead, Write};
+
+use chrono::NaiveDate;
+
+use crate::cinema::{Cinema, CinemaInfo, CinemaRef};
+use crate::request::{DateSpec, MovieMonitorRequest};
+
+/// Asks `question` until `parse` accepts the answer, or `None` once the input ends.
+fn ask<T>(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> io::Result<Option<T>> {
+    loop {
+        write!(output, "{}: ", question)?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            writeln!(output)?;
+            return Ok(None);
+        }
+        match parse(answer.trim()) {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) => writeln!(output, "  {}", e)?,
+        }
+    }
+}
+
+/// A known name or any cinema ID, which has to be on `cinemas` when Pathé's cinema list could be loaded.
+pub(crate) fn parse_cinema(
+    answer: &str,
+    cinemas: Option<&[CinemaInfo]>,
+) -> Result<CinemaRef, String> {
+    let cinema: CinemaRef = answer.parse().map_err(|e| format!("{}", e))?;
+    let id = cinema.id();
+    if let Some(known) = Cinema::ALL.iter().find(|known| known.id() == id) {
+        return Ok(CinemaRef::Known(*known));
+    }
+
+    match cinemas {
+        Some(cinemas) => match cinemas.iter().find(|info| info.id == id) {
+            Some(info) => Ok(CinemaRef::Custom {
+                id,
+                name: Some(info.name.clone()),
+            }),
+            None => Err(format!("cinema ID {} is not on the Pathé cinema list", id)),
+        },
+        None => Ok(cinema),
+    }
+}
+
+/// Asks for one request after the other, until the input ends or no other request is wanted.
+pub(crate) fn prompt_requests(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    cinemas: Option<&[CinemaInfo]>,
+    today: NaiveDate,
+) -> io::Result<Vec<MovieMonitorRequest>> {
+    match cinemas {
+        Some(cinemas) => {
+            writeln!(output, "Pathé lists these cinemas:")?;
+            for cinema in cinemas {
+                writeln!(output, "  {}", cinema)?;
+            }
+        }
+        None => writeln!(
+            output,
+            "The Pathé cinema list could not be loaded, any cinema ID is accepted"
+        )?,
+    }
+
+    let mut requests = vec![];
+    while let Some(cinema) = ask(input, output, "Cinema (name or ID)", |answer| {
+        parse_cinema(answer, cinemas)
+    })? {
+        let date = match ask(
+            input,
+            output,
+            "Date (DD-MM-YYYY, today, tomorrow or +<days>d)",
+            |answer| match DateSpec::Single(answer.to_string()).resolve(today) {
+                Ok(dates) if dates.is_empty() => Err(format!("{} has already passed", answer)),
+                Ok(_) => Ok(answer.to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+        )? {
+            Some(date) => date,
+            None => break,
+        };
+        let movie = match ask(input, output, "Movie", |answer| match answer {
+            "" => Err("the movie can't be empty, use * for every new movie".to_string()),
+            movie => Ok(movie.to_string()),
+        })? {
+            Some(movie) => movie,
+            None => break,
+        };
+
+        let request: MovieMonitorRequest =
+            serde_json::from_value(json!({ "cinema": cinema, "date": date, "movie": movie }))?;
+        match request.validate(today) {
+            Ok(()) => {
+                writeln!(output, "Adding {}", request)?;
+                requests.push(request);
+            }
+            Err(e) => writeln!(output, "  Skipping the request: {}", e)?,
+        }
+
+        let another = ask(input, output, "Add another request? [y/N]", |answer| {
+            Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+        })?;
+        if another != Some(true) {
+            break;
+        }
+    }
+
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_asked_again_until_they_are_valid() {
+        let cinemas = vec![
+            CinemaInfo {
+                id: 13,
+                name: "Spuimarkt".to_string(),
+                city: "Den Haag".to_string(),
+            },
+            CinemaInfo {
+                id: 42,
+                name: "Tuschinski".to_string(),
+                city: "Amsterdam".to_string(),
+            },
+        ];
+        let today = NaiveDate::from_ymd(2021, 8, 19);
+        let mut input = "Nowhere\nspuimarkt\n18-08-2021\ntomorrow\n\nDune\nyes\n99\n42\n+3d\nThe Green Knight\nn\n"
+            .as_bytes();
+        let mut output = vec![];
+
+        let requests = prompt_requests(&mut input, &mut output, Some(&cinemas), today).unwrap();
+        let requests: Vec<serde_json::Value> =
+            requests.iter().map(|request| json!(request)).collect();
+        assert_eq!(
+            requests,
+            vec![
+                json!({ "cinema": "Spuimarkt", "date": "tomorrow", "movie": "Dune" }),
+                json!({
+                    "cinema": { "id": 42, "name": "Tuschinski" },
+                    "date": "+3d",
+                    "movie": "The Green Knight"
+                }),
+            ]
+        );
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("unknown cinema `Nowhere`"));
+        assert!(output.contains("18-08-2021 has already passed"));
+        assert!(output.contains("the movie can't be empty"));
+        assert!(output.contains("cinema ID 99 is not on the Pathé cinema list"));
+
+        // a request isn't added until every question about it is answered
+        let mut input = "Delft\ntoday\n".as_bytes();
+        let requests = prompt_requests(&mut input, &mut vec![], None, today).unwrap();
+        assert!(requests.is_empty());
+    }
+}